@@ -0,0 +1,104 @@
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::tracefs as fs;
+
+/// Whether this build can make Finder aliases (`link = "alias"`): only on
+/// macOS, and only with the `macos-alias` feature.
+pub const SUPPORTED: bool = cfg!(all(target_os = "macos", feature = "macos-alias"));
+
+/// Makes a Finder alias for `source` at `target`, through Finder itself, so
+/// it has to be running and dotlink must be allowed to control it.
+#[cfg(all(target_os = "macos", feature = "macos-alias"))]
+pub fn create(source: &Path, target: &Path) -> io::Result<()> {
+    let (Some(dir), Some(name)) = (target.parent(), target.file_name()) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{target:?} has no parent directory to put an alias in"),
+        ));
+    };
+
+    // Finder names a new alias after its original, it is renamed afterwards
+    osascript(
+        r#"on run argv
+tell application "Finder"
+set made to make new alias file at (POSIX file (item 2 of argv) as alias) to (POSIX file (item 1 of argv) as alias)
+set name of made to item 3 of argv
+end tell
+end run"#,
+        &[source.as_os_str(), dir.as_os_str(), name],
+    )
+    .map(|_| ())
+}
+
+/// What the Finder alias at `target` refers to, `None` if it is not an alias.
+#[cfg(all(target_os = "macos", feature = "macos-alias"))]
+pub fn original(target: &Path) -> io::Result<Option<PathBuf>> {
+    let original = osascript(
+        r#"on run argv
+tell application "Finder"
+set found to item (POSIX file (item 1 of argv) as text)
+if class of found is not alias file then return ""
+return POSIX path of (original item of found as alias)
+end tell
+end run"#,
+        &[target.as_os_str()],
+    )?;
+
+    if original.is_empty() {
+        return Ok(None);
+    }
+
+    // directories come back with a trailing slash
+    let original = original
+        .strip_suffix('/')
+        .filter(|dir| !dir.is_empty())
+        .unwrap_or(&original);
+    Ok(Some(PathBuf::from(original)))
+}
+
+/// Runs an AppleScript with `args` as `argv` and returns what it printed.
+#[cfg(all(target_os = "macos", feature = "macos-alias"))]
+fn osascript(script: &str, args: &[&std::ffi::OsStr]) -> io::Result<String> {
+    let mut command = std::process::Command::new("osascript");
+    for line in script.lines() {
+        command.arg("-e").arg(line);
+    }
+    let output = command.args(args).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "osascript failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .trim_end_matches('\n')
+        .to_string())
+}
+
+#[cfg(not(all(target_os = "macos", feature = "macos-alias")))]
+pub fn create(_source: &Path, _target: &Path) -> io::Result<()> {
+    Err(unsupported())
+}
+
+#[cfg(not(all(target_os = "macos", feature = "macos-alias")))]
+pub fn original(_target: &Path) -> io::Result<Option<PathBuf>> {
+    Err(unsupported())
+}
+
+#[cfg(not(all(target_os = "macos", feature = "macos-alias")))]
+fn unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Finder aliases need dotlink built on macOS with the `macos-alias` feature",
+    )
+}
+
+/// Whether an alias referring to `original` is one for `source`. Finder
+/// reports resolved paths (`/private/tmp` for `/tmp`), so `source` is
+/// compared resolved too.
+pub fn leads_to(original: &Path, source: &Path) -> bool {
+    original == source || fs::canonicalize(source).is_ok_and(|source| source == original)
+}
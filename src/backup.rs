@@ -0,0 +1,138 @@
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::since;
+use crate::theme;
+use crate::tracefs as fs;
+
+/// How many backups of the config are kept when `settings.config_backups` is not set.
+pub const KEEP: usize = 10;
+
+/// The configs already backed up by this run, see `snapshot`.
+static TAKEN: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// A copy of the config saved before it was rewritten.
+pub struct Backup {
+    /// The time it was taken, `20240131T120000Z`, with `-2` and so on added
+    /// when there are several in the same second
+    pub id: String,
+    pub path: PathBuf,
+}
+
+/// Where the backups of the config at `cfg_path` go, `.dotlink/backups` next
+/// to it, or next to the file it points to if it is a symlink.
+pub fn dir(cfg_path: &Path) -> PathBuf {
+    real(cfg_path)
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join(".dotlink")
+        .join("backups")
+}
+
+fn real(cfg_path: &Path) -> PathBuf {
+    fs::canonicalize(cfg_path).unwrap_or_else(|_| cfg_path.to_path_buf())
+}
+
+fn stem(cfg_path: &Path) -> String {
+    real(cfg_path)
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// The backups of the config at `cfg_path`, oldest first.
+pub fn list(cfg_path: &Path) -> io::Result<Vec<Backup>> {
+    let prefix = format!("{}.", stem(cfg_path));
+    let children = match fs::read_dir(dir(cfg_path)) {
+        Ok(children) => children,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut backups = Vec::new();
+    for child in children {
+        let path = child?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        if let Some(id) = name.strip_prefix(&prefix).and_then(|rest| rest.strip_suffix(".toml")) {
+            backups.push(Backup {
+                id: id.to_string(),
+                path,
+            });
+        }
+    }
+
+    backups.sort_by(|a, b| order(&a.id).cmp(&order(&b.id)));
+    Ok(backups)
+}
+
+/// Sort key of a backup id: its time, then the number of the backup within
+/// that second, so `-10` comes after `-9`.
+fn order(id: &str) -> (&str, u32) {
+    match id.split_once('-') {
+        Some((time, n)) => (time, n.parse().unwrap_or(u32::MAX)),
+        None => (id, 1),
+    }
+}
+
+/// Copies the config at `cfg_path` into the backups unless it is about to be
+/// replaced by the same `contents`, then removes the oldest ones beyond
+/// `keep`. Failures are only warned about, they never stop a command.
+///
+/// Only the first rewrite of a run is backed up: `add` saves after every
+/// file, and a batch larger than `keep` would otherwise rotate out the one
+/// copy worth having, the config as it was before the command.
+pub fn snapshot(cfg_path: &Path, contents: &str, keep: usize) {
+    if keep == 0 {
+        return;
+    }
+
+    let real = real(cfg_path);
+    let mut taken = TAKEN.lock().unwrap_or_else(|e| e.into_inner());
+    if taken.contains(&real) {
+        return;
+    }
+
+    match try_snapshot(cfg_path, contents, keep) {
+        Ok(true) => taken.push(real),
+        Ok(false) => {}
+        Err(e) => eprintln!("  {} Cannot back up the config: {e}", theme::warning("Warning:")),
+    }
+}
+
+/// Whether a backup was taken, there is none when nothing would change.
+fn try_snapshot(cfg_path: &Path, contents: &str, keep: usize) -> io::Result<bool> {
+    let current = match fs::read_to_string(cfg_path) {
+        Ok(current) if current == contents => return Ok(false),
+        Ok(current) => current,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+
+    let dir = dir(cfg_path);
+    fs::create_dir_all(&dir)?;
+
+    let time: String = since::format_utc(SystemTime::now())
+        .chars()
+        .filter(|c| !matches!(c, '-' | ':'))
+        .collect();
+    let name = |n: u32| match n {
+        1 => format!("{}.{time}.toml", stem(cfg_path)),
+        n => format!("{}.{time}-{n}.toml", stem(cfg_path)),
+    };
+    let path = (1..)
+        .map(|n| dir.join(name(n)))
+        .find(|path| fs::symlink_metadata(path).is_err())
+        .expect("some numbered name is free");
+    fs::write_synced(&path, current)?;
+
+    let backups = list(cfg_path)?;
+    for old in &backups[..backups.len().saturating_sub(keep)] {
+        fs::remove_file(&old.path)?;
+    }
+
+    Ok(true)
+}
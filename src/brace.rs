@@ -0,0 +1,121 @@
+/// Expands shell-style brace groups in a pattern: `~/.config/{nvim,tmux}`
+/// becomes `~/.config/nvim` and `~/.config/tmux`.
+///
+/// Groups may be nested (`{a,b{1,2}}`), and a group without a comma is kept
+/// as it is, like in bash. `\{`, `\}` and `\,` stand for the literal
+/// characters, and `${VAR}` is left alone for variable expansion.
+pub fn expand(pattern: &str) -> Vec<String> {
+    let Some((start, end, alternatives)) = find_group(pattern) else {
+        return vec![unescape(pattern)];
+    };
+
+    let (prefix, suffix) = (&pattern[..start], &pattern[end + 1..]);
+    alternatives
+        .iter()
+        .flat_map(|alt| expand(&format!("{prefix}{alt}{suffix}")))
+        .collect()
+}
+
+/// Finds the first brace group with at least one top-level comma and returns
+/// the byte offsets of its braces and its alternatives.
+fn find_group(pattern: &str) -> Option<(usize, usize, Vec<&str>)> {
+    let bytes = pattern.as_bytes();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'{' if i == 0 || bytes[i - 1] != b'$' => {
+                if let Some((end, alternatives)) = split_group(pattern, i) {
+                    return Some((i, end, alternatives));
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    None
+}
+
+/// Splits the group opening at `start` on its top-level commas, `None` if it
+/// is never closed or has no comma.
+fn split_group(pattern: &str, start: usize) -> Option<(usize, Vec<&str>)> {
+    let bytes = pattern.as_bytes();
+    let mut depth = 0;
+    let mut alternatives = Vec::new();
+    let mut from = start + 1;
+    let mut i = start + 1;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'{' => depth += 1,
+            b'}' if depth > 0 => depth -= 1,
+            b'}' => {
+                if alternatives.is_empty() {
+                    return None;
+                }
+                alternatives.push(&pattern[from..i]);
+                return Some((i, alternatives));
+            }
+            b',' if depth == 0 => {
+                alternatives.push(&pattern[from..i]);
+                from = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    None
+}
+
+fn unescape(pattern: &str) -> String {
+    let mut out = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(&next @ ('{' | '}' | ','))) => {
+                out.push(next);
+                chars.next();
+            }
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_groups() {
+        assert_eq!(expand("a{b,{c,d}}"), ["ab", "ac", "ad"]);
+        assert_eq!(
+            expand("~/.config/{nvim,tmux}"),
+            ["~/.config/nvim", "~/.config/tmux"]
+        );
+    }
+
+    #[test]
+    fn escaped_braces_stay_literal() {
+        assert_eq!(expand(r"\{x,y\}"), ["{x,y}"]);
+        assert_eq!(expand(r"a{b\,c,d}"), ["ab,c", "ad"]);
+    }
+
+    #[test]
+    fn unbalanced_brace_is_kept() {
+        assert_eq!(expand("a{b,c"), ["a{b,c"]);
+        assert_eq!(expand("a}b,c{"), ["a}b,c{"]);
+    }
+
+    #[test]
+    fn group_without_comma_and_variables_are_kept() {
+        assert_eq!(expand("a{b}"), ["a{b}"]);
+        assert_eq!(expand("${HOME}/{a,b}"), ["${HOME}/a", "${HOME}/b"]);
+    }
+}
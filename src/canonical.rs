@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+use path_clean::PathClean;
+
+use crate::config::Config;
+use crate::config::Entry;
+use crate::error::Error;
+use crate::error::Result;
+
+/// Rewrites the paths of the main config's entries, profiles and `target_base`
+/// into their canonical form, see `normalize_path`.
+///
+/// Fails if two entries end up with the same source.
+pub fn normalize(cfg: &mut Config) -> Result<()> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let home = home
+        .as_deref()
+        .filter(|home| home.is_absolute() && home.parent().is_some());
+
+    cfg.settings.target_base = cfg
+        .settings
+        .target_base
+        .as_deref()
+        .map(|base| normalize_path(base, home));
+
+    cfg.entries = normalize_entries(std::mem::take(&mut cfg.entries), home)?;
+    for profile in cfg.profiles.values_mut() {
+        profile.entries = normalize_entries(std::mem::take(&mut profile.entries), home)?;
+    }
+
+    Ok(())
+}
+
+fn normalize_entries(
+    entries: BTreeMap<PathBuf, Entry>,
+    home: Option<&Path>,
+) -> Result<BTreeMap<PathBuf, Entry>> {
+    let mut normalized: BTreeMap<PathBuf, (PathBuf, Entry)> = BTreeMap::new();
+    for (source, mut entry) in entries {
+        for target in &mut entry.targets {
+            *target = normalize_path(target, home);
+        }
+
+        let key = normalize_path(&source, home);
+        if let Some((other, _)) = normalized.get(&key) {
+            return Err(Error::DuplicateEntry {
+                entry: key,
+                first: format!("{other:?}"),
+                second: format!("{source:?}"),
+            });
+        }
+        normalized.insert(key, (source, entry));
+    }
+
+    Ok(normalized
+        .into_iter()
+        .map(|(key, (_, entry))| (key, entry))
+        .collect())
+}
+
+/// Cleans `path` lexically (`a/./b/../c` becomes `a/c`) and writes absolute
+/// paths under `home` with a leading `~`.
+///
+/// A `..` after `~` or a variable is left alone, it can't be resolved without
+/// expanding them first.
+fn normalize_path(path: &Path, home: Option<&Path>) -> PathBuf {
+    let raw = path.to_string_lossy();
+    if (raw.starts_with('~') || raw.contains('$'))
+        && path.components().any(|c| c == Component::ParentDir)
+    {
+        return path.to_path_buf();
+    }
+
+    let path = path.clean();
+    match home.and_then(|home| path.strip_prefix(home).ok()) {
+        Some(rest) if rest.as_os_str().is_empty() => PathBuf::from("~"),
+        Some(rest) => Path::new("~").join(rest),
+        None => path,
+    }
+}
+
+/// A line of `diff`'s output.
+pub enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Line-based diff of `old` and `new`, from their longest common subsequence.
+pub fn diff<'a>(old: &'a str, new: &'a str) -> Vec<Line<'a>> {
+    let old: Vec<_> = old.lines().collect();
+    let new: Vec<_> = new.lines().collect();
+
+    // lcs[i][j]: length of the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(Line::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(Line::Removed(old[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(new[j]));
+            j += 1;
+        }
+    }
+    lines
+}
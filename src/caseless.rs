@@ -0,0 +1,108 @@
+use std::path::Path;
+
+use glob::MatchOptions;
+use glob::Pattern;
+use path_clean::PathClean;
+
+use crate::brace;
+use crate::error::Error;
+use crate::error::Result;
+use crate::expand::expand_path;
+
+// `*` stays within one component, like glob on disk does
+const OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: false,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// The patterns of `unlink --ignore-case`. Unlike normal patterns they are not
+/// looked up on disk, where glob only finds names in their exact case, but
+/// matched against the sources and targets of the entries.
+pub struct Patterns {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl Patterns {
+    /// Expands `patterns` like `resolve_patterns` does, those starting with `!`
+    /// exclude what they match.
+    pub fn new(patterns: &[String]) -> Result<Self> {
+        let mut include = Vec::new();
+        let mut exclude = Vec::new();
+        for pattern in patterns {
+            let (list, pattern) = match pattern.strip_prefix('!') {
+                Some(pattern) => (&mut exclude, pattern),
+                None => (&mut include, pattern.as_str()),
+            };
+
+            for expanded in brace::expand(pattern) {
+                let path = std::path::absolute(expand_path(Path::new(&expanded))?)?.clean();
+                let compiled = Pattern::new(&path.to_string_lossy()).map_err(|source| {
+                    Error::InvalidPattern {
+                        pattern: pattern.to_string(),
+                        source,
+                    }
+                })?;
+                list.push(compiled);
+            }
+        }
+
+        Ok(Self { include, exclude })
+    }
+
+    /// Whether `path` matches one of the patterns, ignoring case, and none of
+    /// the excluding ones.
+    pub fn matches(&self, path: &Path) -> bool {
+        let matches = |pattern: &Pattern| pattern.matches_path_with(path, OPTIONS);
+        self.include.iter().any(matches) && !self.exclude.iter().any(matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(patterns: &[&str]) -> Patterns {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        Patterns::new(&patterns).unwrap()
+    }
+
+    #[test]
+    fn ignores_case_of_pattern_and_path() {
+        let patterns = patterns(&["/home/x/.Config/NVim"]);
+        assert!(patterns.matches(Path::new("/home/x/.config/nvim")));
+        assert!(patterns.matches(Path::new("/HOME/X/.CONFIG/NVIM")));
+        assert!(!patterns.matches(Path::new("/home/x/.config/nvim2")));
+    }
+
+    #[test]
+    fn wildcards_ignore_case() {
+        let patterns = patterns(&["/home/x/.config/Alacritty*"]);
+        assert!(patterns.matches(Path::new("/home/x/.config/alacritty")));
+        assert!(patterns.matches(Path::new("/home/x/.config/ALACRITTY.yml")));
+    }
+
+    #[test]
+    fn star_does_not_cross_separators() {
+        let patterns = patterns(&["/home/x/*"]);
+        assert!(patterns.matches(Path::new("/home/x/.vimrc")));
+        assert!(!patterns.matches(Path::new("/home/x/.config/a/deep.conf")));
+    }
+
+    #[test]
+    fn exclusions_ignore_case_too() {
+        let patterns = patterns(&["/home/x/.config/*", "!/home/x/.config/NVIM"]);
+        assert!(patterns.matches(Path::new("/home/x/.config/tmux")));
+        assert!(!patterns.matches(Path::new("/home/x/.config/nvim")));
+    }
+
+    #[test]
+    fn invalid_pattern_is_an_error() {
+        let patterns = vec!["/home/x/[abc".to_string()];
+        assert!(matches!(
+            Patterns::new(&patterns),
+            Err(Error::InvalidPattern { pattern, .. }) if pattern == "/home/x/[abc"
+        ));
+    }
+}
@@ -0,0 +1,49 @@
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::theme;
+
+/// The files in `root` that git reports as changed since `rev`: committed
+/// since then, modified in the working tree, or new and not ignored. `None`,
+/// after a warning, if git cannot tell, in which case everything counts as
+/// changed.
+pub fn since_commit(root: &Path, rev: &str) -> Option<Vec<PathBuf>> {
+    let result = git(root, &["diff", "--name-only", "-z", "--relative", rev, "--"]).and_then(|mut changed| {
+        changed.extend(git(root, &["ls-files", "--others", "--exclude-standard", "-z"])?);
+        Ok(changed)
+    });
+
+    match result {
+        Ok(changed) => Some(changed),
+        Err(e) => {
+            eprintln!(
+                "  {} Cannot ask git what changed since `{rev}` ({e}), processing every entry.",
+                theme::warning("Warning:")
+            );
+            None
+        }
+    }
+}
+
+/// Runs git in `root` and returns the NUL-separated paths it prints, made absolute.
+fn git(root: &Path, args: &[&str]) -> Result<Vec<PathBuf>, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(args)
+        .output()
+        .map_err(|e| format!("cannot run git: {e}"))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+
+    Ok(output
+        .stdout
+        .split(|b| *b == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| root.join(OsStr::from_bytes(path)))
+        .collect())
+}
@@ -0,0 +1,1104 @@
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fmt;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+use glob::glob;
+use path_clean::PathClean;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::CFG_FILE_ENV_VAR;
+use crate::alias;
+use crate::backup;
+use crate::STOP_MARKER;
+use crate::error::Error;
+use crate::error::Result;
+use crate::expand;
+use crate::expand::expand_path;
+use crate::link;
+use crate::link::DirMode;
+use crate::link::LinkKind;
+use crate::mode::Mode;
+use crate::runlog::LogFile;
+use crate::template;
+use crate::theme;
+use crate::theme::Colors;
+use crate::tracefs as fs;
+
+/// A commented example config with every supported key, printed by `config-schema`.
+/// Keep it in sync with `Config`, `Settings`, `Colors` and `Entry`.
+pub const SCHEMA: &str = include_str!("schema.toml");
+
+/// Version of the config format this dotlink reads and writes, see `migrate`.
+pub const CONFIG_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+    /// Version of the config format, 1 when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
+    /// Extra config files (globs allowed) whose entries are merged into this one,
+    /// relative to the directory of this config
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    pub settings: Settings,
+    #[serde(default)]
+    pub entries: BTreeMap<PathBuf, Entry>,
+    /// Named sets of entries, the selected one is merged over `entries`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
+    /// Name of the profile selected with `select_profile`.
+    #[serde(skip)]
+    pub active_profile: Option<String>,
+    /// Entries pulled in through `include`, with the file each one came from.
+    /// Never written back, rewrites of the main config only touch `entries`.
+    #[serde(skip)]
+    pub included: BTreeMap<PathBuf, (PathBuf, Entry)>,
+    /// The `.dotlink-root` file found by `read_root_marker` and the root it
+    /// names, used when the config has none. Never written back.
+    #[serde(skip)]
+    pub root_marker: Option<(PathBuf, PathBuf)>,
+    /// Environment variable naming the root, from `--root-env`. Never written back.
+    #[serde(skip)]
+    pub root_env: Option<String>,
+    /// Root found by `infer_git_root`, used when neither the config nor the
+    /// environment name one. Never written back.
+    #[serde(skip)]
+    pub git_root: Option<PathBuf>,
+    /// The machine-local config loaded by `load_local`, if there is one.
+    #[serde(skip)]
+    pub local: Option<Local>,
+}
+
+/// `Link.local.toml` next to the main config: settings and entries for this
+/// machine only, laid over the shared ones. Its entries are kept in `included`.
+#[derive(Debug, Clone)]
+pub struct Local {
+    pub path: PathBuf,
+    /// The settings the local file sets
+    pub settings: Settings,
+    /// The settings of the main config, before the local ones were laid over them
+    pub shared: Settings,
+}
+
+/// The contents of a local config.
+#[derive(Deserialize)]
+struct LocalConfig {
+    #[serde(default)]
+    settings: Settings,
+    #[serde(default)]
+    entries: BTreeMap<PathBuf, Entry>,
+}
+
+/// `[profiles.<name>]`
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    #[serde(default)]
+    pub entries: BTreeMap<PathBuf, Entry>,
+}
+
+/// A config file pulled in through `include`, only entries and further includes are read.
+#[derive(Deserialize)]
+struct IncludedConfig {
+    #[serde(default)]
+    include: Vec<String>,
+    #[serde(default)]
+    entries: BTreeMap<PathBuf, Entry>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Settings {
+    pub dotlink_root: Option<PathBuf>,
+    /// Without a root here or in the environment, use the top level of the
+    /// git checkout around the working directory, like `--root-from-git`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub root_from_git: bool,
+    /// Profile used when `--profile` is not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_profile: Option<String>,
+    /// Directory relative targets are placed under, `$HOME` if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_base: Option<PathBuf>,
+    /// Kind of link made for entries without their own `link` (default `symlink`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_link: Option<LinkKind>,
+    /// Prefixes `add` writes targets with, e.g. `$XDG_CONFIG_HOME` (default `["~"]`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub portable_prefixes: Option<Vec<String>>,
+    /// How often `fix` retries reading a target after a transient error (default 2)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+    /// Delay before the first retry, doubled for every further one (default 100)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_delay_ms: Option<u64>,
+    /// How many backups of the config to keep, 0 for none (default 10)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_backups: Option<usize>,
+    /// Mode of the directories dotlink creates for links and added files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir_perms: Option<Mode>,
+    /// Append a log of every run, `true` for the default location or a path
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_file: Option<LogFile>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub colors: Option<Colors>,
+}
+
+impl Settings {
+    /// Replaces every setting `over` sets with the value it has in `from`.
+    fn overlay(&mut self, over: &Settings, from: &Settings) {
+        fn take<T: Clone>(setting: &mut Option<T>, over: &Option<T>, from: &Option<T>) {
+            if over.is_some() {
+                setting.clone_from(from);
+            }
+        }
+
+        take(&mut self.dotlink_root, &over.dotlink_root, &from.dotlink_root);
+        if over.root_from_git {
+            self.root_from_git = from.root_from_git;
+        }
+        take(&mut self.default_profile, &over.default_profile, &from.default_profile);
+        take(&mut self.target_base, &over.target_base, &from.target_base);
+        take(&mut self.default_link, &over.default_link, &from.default_link);
+        take(&mut self.portable_prefixes, &over.portable_prefixes, &from.portable_prefixes);
+        take(&mut self.retries, &over.retries, &from.retries);
+        take(&mut self.retry_delay_ms, &over.retry_delay_ms, &from.retry_delay_ms);
+        take(&mut self.config_backups, &over.config_backups, &from.config_backups);
+        take(&mut self.dir_perms, &over.dir_perms, &from.dir_perms);
+        take(&mut self.log_file, &over.log_file, &from.log_file);
+        take(&mut self.colors, &over.colors, &from.colors);
+    }
+}
+
+/// The value side of an `[entries]` mapping.
+///
+/// Written either as a plain target path or, when options are needed, as a table:
+///
+/// ```toml
+/// "/home/user/dotfiles/hosts" = { target = "/etc/hosts", privileged = true }
+/// ```
+///
+/// Either form takes a list of targets instead, to link the source at each of them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "EntryRepr", into = "EntryRepr")]
+pub struct Entry {
+    /// Where the source is linked, at least one
+    pub targets: Vec<PathBuf>,
+    /// Perform filesystem changes for this entry through `sudo`
+    pub privileged: bool,
+    /// Disabled entries stay in the config but are skipped by `fix` and `check`
+    pub enabled: bool,
+    /// Permissions `fix` gives the source, overriding `--chmod-files`/`--chmod-dirs`
+    pub mode: Option<Mode>,
+    /// User `fix` makes the owner of the source, a name or a uid
+    pub owner: Option<String>,
+    /// Group `fix` gives the source, a name or a gid
+    pub group: Option<String>,
+    /// Kind of link for this entry, overriding `settings.default_link`
+    pub link: Option<LinkKind>,
+    /// Whether a directory source is linked as a whole or folded into links for its files
+    pub dir_mode: Option<DirMode>,
+    /// Shell command `fix` runs before creating or replacing a link of this entry
+    pub pre_link: Option<String>,
+    /// Shell command `fix` runs after creating or replacing a link of this entry
+    pub post_link: Option<String>,
+    /// Short name to refer to the entry by on the command line
+    pub alias: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum EntryRepr {
+    Target(Targets),
+    Table {
+        target: Targets,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        privileged: bool,
+        #[serde(default = "enabled_default", skip_serializing_if = "is_enabled")]
+        enabled: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mode: Option<Mode>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        owner: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        group: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        link: Option<LinkKind>,
+        // a string, so a typo is reported as such rather than as an entry of no known form
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        dir_mode: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pre_link: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        post_link: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        alias: Option<String>,
+    },
+}
+
+/// `target` as written, a single path or a list of them.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Targets {
+    One(PathBuf),
+    Many(Vec<PathBuf>),
+}
+
+impl From<Targets> for Vec<PathBuf> {
+    fn from(targets: Targets) -> Self {
+        match targets {
+            Targets::One(target) => vec![target],
+            Targets::Many(targets) => targets,
+        }
+    }
+}
+
+impl From<Vec<PathBuf>> for Targets {
+    fn from(mut targets: Vec<PathBuf>) -> Self {
+        match targets.len() {
+            1 => Targets::One(targets.remove(0)),
+            _ => Targets::Many(targets),
+        }
+    }
+}
+
+fn enabled_default() -> bool {
+    true
+}
+
+fn is_enabled(enabled: &bool) -> bool {
+    *enabled
+}
+
+impl TryFrom<EntryRepr> for Entry {
+    type Error = String;
+
+    fn try_from(repr: EntryRepr) -> Result<Self, String> {
+        let entry = match repr {
+            EntryRepr::Target(target) => Entry {
+                targets: target.into(),
+                privileged: false,
+                enabled: true,
+                mode: None,
+                owner: None,
+                group: None,
+                link: None,
+                dir_mode: None,
+                pre_link: None,
+                post_link: None,
+                alias: None,
+            },
+            EntryRepr::Table {
+                target,
+                privileged,
+                enabled,
+                mode,
+                owner,
+                group,
+                link,
+                dir_mode,
+                pre_link,
+                post_link,
+                alias,
+            } => Entry {
+                targets: target.into(),
+                privileged,
+                enabled,
+                mode,
+                owner,
+                group,
+                link,
+                dir_mode: dir_mode.as_deref().map(DirMode::parse).transpose()?,
+                pre_link,
+                post_link,
+                alias,
+            },
+        };
+
+        if entry.targets.is_empty() {
+            return Err("the target list of an entry is empty".to_string());
+        }
+        Ok(entry)
+    }
+}
+
+impl From<Entry> for EntryRepr {
+    fn from(entry: Entry) -> Self {
+        // keep the short form for entries without any options
+        if entry.privileged
+            || !entry.enabled
+            || entry.mode.is_some()
+            || entry.owner.is_some()
+            || entry.group.is_some()
+            || entry.link.is_some()
+            || entry.dir_mode.is_some()
+            || entry.pre_link.is_some()
+            || entry.post_link.is_some()
+            || entry.alias.is_some()
+        {
+            EntryRepr::Table {
+                target: entry.targets.into(),
+                privileged: entry.privileged,
+                enabled: entry.enabled,
+                mode: entry.mode,
+                owner: entry.owner,
+                group: entry.group,
+                link: entry.link,
+                dir_mode: entry.dir_mode.map(|mode| mode.as_str().to_string()),
+                pre_link: entry.pre_link,
+                post_link: entry.post_link,
+                alias: entry.alias,
+            }
+        } else {
+            EntryRepr::Target(entry.targets.into())
+        }
+    }
+}
+
+impl Entry {
+    pub fn new(target: PathBuf) -> Self {
+        Self {
+            targets: vec![target],
+            privileged: false,
+            enabled: true,
+            mode: None,
+            owner: None,
+            group: None,
+            link: None,
+            dir_mode: None,
+            pre_link: None,
+            post_link: None,
+            alias: None,
+        }
+    }
+}
+
+/// An entry with its source resolved against the dotfiles root, one for each
+/// of its targets.
+pub struct ResolvedEntry {
+    /// Source path as written in the config (or as matched, for templates)
+    pub name: PathBuf,
+    /// Absolute path of the source inside the dotfiles root
+    pub source: PathBuf,
+    /// Target path, not yet expanded
+    pub target: PathBuf,
+    pub privileged: bool,
+    pub enabled: bool,
+    pub mode: Option<Mode>,
+    pub owner: Option<String>,
+    pub group: Option<String>,
+    pub link: LinkKind,
+    /// Whether this is one of the files of a folded directory
+    pub folded: bool,
+    pub pre_link: Option<String>,
+    pub post_link: Option<String>,
+    pub alias: Option<String>,
+    /// Which part of the config the entry comes from
+    pub origin: Origin,
+}
+
+/// Where an entry in effect was defined, for `--explain`.
+#[derive(Clone)]
+pub enum Origin {
+    /// `[entries]` of the main config
+    Base,
+    /// `[profiles.<name>.entries]` of the active profile, and whether it
+    /// replaces an entry of `[entries]`
+    Profile { name: String, replaces: bool },
+    /// An included file
+    Include(PathBuf),
+    /// The machine-local config, and whether it replaces an entry of the main config
+    Local { path: PathBuf, replaces: bool },
+}
+
+impl Origin {
+    /// Why an entry from here is linked or, if it is not `enabled`, skipped.
+    pub fn explain(&self, enabled: bool) -> String {
+        if enabled {
+            self.to_string()
+        } else {
+            format!("skipped: `enabled = false`, {self}")
+        }
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Origin::Base => write!(f, "from [entries]"),
+            Origin::Profile { name, replaces } => {
+                write!(f, "from [profiles.{name}.entries], profile `{name}` is active")?;
+                if *replaces {
+                    write!(f, ", replacing the entry in [entries]")?;
+                }
+                Ok(())
+            }
+            Origin::Include(path) => write!(f, "from {path:?}, included"),
+            Origin::Local { path, replaces } => {
+                write!(f, "from {path:?}")?;
+                if *replaces {
+                    write!(f, ", replacing the entry in the main config")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Config {
+    pub fn get_root(&self) -> Result<PathBuf> {
+        let root = self.configured_root()?;
+        fs::canonicalize(&root).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => Error::RootNotFound(root),
+            _ => e.into(),
+        })
+    }
+
+    /// The root as configured, without requiring it to exist. In order of
+    /// precedence: `settings.dotlink_root`, a `.dotlink-root` file, `DOTLINK_ROOT`
+    /// (or the variable given with `--root-env`) and the git checkout.
+    pub fn configured_root(&self) -> Result<PathBuf> {
+        match &self.settings.dotlink_root {
+            Some(root) => Ok(root.clone()),
+            None => self
+                .root_marker
+                .as_ref()
+                .map(|(_, root)| root.clone())
+                .or_else(|| std::env::var_os(self.root_env()).map(PathBuf::from))
+                .or_else(|| self.git_root.clone())
+                .ok_or_else(|| Error::MissingRoot(self.root_env().to_string())),
+        }
+    }
+
+    /// The environment variable naming the root, `DOTLINK_ROOT` unless
+    /// `--root-env` gives another.
+    pub fn root_env(&self) -> &str {
+        self.root_env.as_deref().unwrap_or(CFG_FILE_ENV_VAR)
+    }
+
+    /// Sets `root_marker` from the nearest `.dotlink-root` file in `dir` or its
+    /// parents, unless the config names a root. The file holds the root, with `~`
+    /// and variables expanded and relative paths taken from the file's directory.
+    /// An empty marker only ends the search, like it does for the config.
+    pub fn read_root_marker(&mut self, dir: &Path) -> Result<()> {
+        if self.settings.dotlink_root.is_some() {
+            return Ok(());
+        }
+
+        let Some(path) = dir.ancestors().map(|dir| dir.join(STOP_MARKER)).find(|p| p.exists()) else {
+            return Ok(());
+        };
+        if !path.is_file() {
+            return Ok(());
+        }
+
+        let root = fs::read_to_string(&path)
+            .and_then(|contents| expand_path(Path::new(contents.trim())))
+            .map_err(|source| Error::RootMarker {
+                path: path.clone(),
+                source,
+            })?;
+        if !root.as_os_str().is_empty() {
+            let dir = path.parent().expect("marker is in a directory");
+            self.root_marker = Some((path.clone(), dir.join(root).clean()));
+        }
+
+        Ok(())
+    }
+
+    /// Sets `git_root` to the top level of the git checkout around the working
+    /// directory, unless the config, a `.dotlink-root` file or the environment
+    /// already name a root.
+    pub fn infer_git_root(&mut self) -> Result<()> {
+        if self.settings.dotlink_root.is_some()
+            || self.root_marker.is_some()
+            || std::env::var_os(self.root_env()).is_some()
+        {
+            return Ok(());
+        }
+
+        let output = Command::new("git")
+            .args(["rev-parse", "--show-toplevel"])
+            .output()
+            .map_err(|e| Error::GitRoot(format!("cannot run git: {e}")))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(Error::GitRoot(stderr.trim().to_string()));
+        }
+
+        let toplevel = OsStr::from_bytes(output.stdout.trim_ascii_end());
+        self.git_root = Some(PathBuf::from(toplevel));
+        Ok(())
+    }
+
+    /// How many backups of the config to keep, `settings.config_backups` or `backup::KEEP`.
+    pub fn backups_kept(&self) -> usize {
+        self.settings.config_backups.unwrap_or(backup::KEEP)
+    }
+
+    /// Kind of link made for entries without their own, `settings.default_link` or a symlink.
+    pub fn link_kind(&self) -> LinkKind {
+        self.settings.default_link.unwrap_or_default()
+    }
+
+    /// Expands `target` (built-in variables first) and, if it is relative, places
+    /// it under `settings.target_base`.
+    pub fn resolve_target(&self, target: &Path) -> io::Result<PathBuf> {
+        let target = expand_path(&template::expand_builtins(target)?)?;
+        if target.is_absolute() {
+            return Ok(target.clean());
+        }
+
+        Ok(self.target_base()?.join(target).clean())
+    }
+
+    /// Where relative targets are placed, `settings.target_base` or the home directory.
+    pub fn target_base(&self) -> io::Result<PathBuf> {
+        Ok(expand_path(self.settings.target_base.as_deref().unwrap_or(Path::new("~")))?.clean())
+    }
+
+    /// `target` (absolute) written with the prefix from `settings.portable_prefixes`
+    /// that covers the most of it, or as it is if none does. Prefixes that cannot
+    /// be expanded on this machine are ignored, the rest of the path is escaped.
+    pub fn portable_target(&self, target: &Path) -> PathBuf {
+        let default = ["~".to_string()];
+        let prefixes = self.settings.portable_prefixes.as_deref().unwrap_or(&default);
+
+        prefixes
+            .iter()
+            .filter_map(|prefix| {
+                let expanded = expand_path(Path::new(prefix)).ok()?.clean();
+                if !expanded.is_absolute() || expanded.parent().is_none() {
+                    return None;
+                }
+
+                let portable = match target.strip_prefix(&expanded).ok()? {
+                    rest if rest.as_os_str().is_empty() => PathBuf::from(prefix),
+                    rest => Path::new(prefix).join(expand::escape(rest)),
+                };
+                Some((expanded.components().count(), portable))
+            })
+            .max_by_key(|(depth, _)| *depth)
+            .map_or_else(|| expand::escape(target), |(_, portable)| portable)
+    }
+
+    /// Expands `source` and, if it is relative, places it under the dotfiles root `base`.
+    ///
+    /// Absolute sources are allowed, `check` reports the ones outside the root.
+    pub fn resolve_source(base: &Path, source: &Path) -> io::Result<PathBuf> {
+        Ok(base.join(expand_path(source)?).clean())
+    }
+
+    /// Activates the profile `name`, or `settings.default_profile` if `None`.
+    pub fn select_profile(&mut self, name: Option<&str>) -> Result<()> {
+        let Some(name) = name.or(self.settings.default_profile.as_deref()) else {
+            return Ok(());
+        };
+
+        let Some(profile) = self.profiles.get(name) else {
+            let known = self.profiles.keys().map(String::as_str).collect::<Vec<_>>();
+            return Err(Error::InvalidConfig(format!(
+                "unknown profile `{name}` (known: {})",
+                known.join(", ")
+            )));
+        };
+
+        if let Some((source, (file, _))) = self
+            .included
+            .iter()
+            .find(|(source, _)| profile.entries.contains_key(*source) && !self.is_local(source))
+        {
+            return Err(Error::DuplicateEntry {
+                entry: source.clone(),
+                first: format!("profile `{name}`"),
+                second: format!("{file:?}"),
+            });
+        }
+
+        self.active_profile = Some(name.to_string());
+        Ok(())
+    }
+
+    fn profile_entries(&self) -> Option<&BTreeMap<PathBuf, Entry>> {
+        let name = self.active_profile.as_ref()?;
+        Some(&self.profiles.get(name)?.entries)
+    }
+
+    /// The entries of the main config in effect: `entries` with the active
+    /// profile's entries added, which win over `entries` with the same source.
+    pub fn own_entries(&self) -> impl Iterator<Item = (&PathBuf, &Entry)> {
+        let profile = self.profile_entries();
+        self.entries
+            .iter()
+            .filter(move |(source, _)| !profile.is_some_and(|p| p.contains_key(*source)))
+            .chain(profile.into_iter().flatten())
+            .filter(|(source, _)| !self.is_local(source))
+    }
+
+    /// Whether the entry with `source` comes from the local config.
+    fn is_local(&self, source: &Path) -> bool {
+        self.local.as_ref().is_some_and(|local| {
+            self.included
+                .get(source)
+                .is_some_and(|(file, _)| *file == local.path)
+        })
+    }
+
+    /// Which part of the config the entry in effect with `source` comes from.
+    pub fn origin(&self, source: &Path) -> Origin {
+        let in_profile = self.profile_entries().is_some_and(|p| p.contains_key(source));
+        if let Some((file, _)) = self.included.get(source) {
+            if !self.is_local(source) {
+                return Origin::Include(file.clone());
+            }
+            return Origin::Local {
+                path: file.clone(),
+                replaces: in_profile || self.entries.contains_key(source),
+            };
+        }
+
+        match &self.active_profile {
+            Some(name) if in_profile => Origin::Profile {
+                name: name.clone(),
+                replaces: self.entries.contains_key(source),
+            },
+            _ => Origin::Base,
+        }
+    }
+
+    /// The map an entry of `own_entries` with `source` lives in, for changing it.
+    pub fn entries_containing(&mut self, source: &Path) -> &mut BTreeMap<PathBuf, Entry> {
+        match &self.active_profile {
+            Some(name) if self.profiles.get(name).is_some_and(|p| p.entries.contains_key(source)) => {
+                &mut self.profiles.get_mut(name).expect("profile exists").entries
+            }
+            _ => &mut self.entries,
+        }
+    }
+
+    /// Entries of the main config followed by the included ones, as written.
+    pub fn all_entries(&self) -> impl Iterator<Item = (&PathBuf, &Entry)> {
+        self.own_entries()
+            .chain(self.included.iter().map(|(source, (_, entry))| (source, entry)))
+    }
+
+    /// Refuses an `alias` that more than one entry in effect uses.
+    pub fn check_aliases(&self) -> Result<()> {
+        let mut seen: BTreeMap<&str, &PathBuf> = BTreeMap::new();
+        for (source, entry) in self.all_entries() {
+            let Some(alias) = &entry.alias else {
+                continue;
+            };
+            if let Some(first) = seen.insert(alias, source) {
+                return Err(Error::InvalidConfig(format!(
+                    "alias `{alias}` is used by both {first:?} and {source:?}"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Refuses Finder aliases (`link = "alias"`) where they can't be made: in a
+    /// build without them, and for privileged entries, as Finder won't run as root.
+    pub fn check_links(&self) -> Result<()> {
+        if !alias::SUPPORTED && self.settings.default_link == Some(LinkKind::Alias) {
+            return Err(Error::InvalidConfig(
+                "`default_link = \"alias\"` needs dotlink built on macOS with the `macos-alias` feature".to_string(),
+            ));
+        }
+
+        for (source, entry) in self.all_entries() {
+            if entry.link.unwrap_or(self.link_kind()) != LinkKind::Alias {
+                continue;
+            }
+            if !alias::SUPPORTED {
+                return Err(Error::InvalidConfig(format!(
+                    "{source:?} is linked as a Finder alias, which needs dotlink built on macOS with the `macos-alias` feature"
+                )));
+            }
+            if entry.privileged {
+                return Err(Error::InvalidConfig(format!(
+                    "{source:?} is privileged, Finder aliases can't be made as root"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// The sources of the entries in effect with one of `aliases`.
+    pub fn sources_by_alias(&self, aliases: &HashSet<&str>) -> HashSet<PathBuf> {
+        self.all_entries()
+            .filter(|(_, entry)| entry.alias.as_deref().is_some_and(|alias| aliases.contains(alias)))
+            .map(|(source, _)| source.clone())
+            .collect()
+    }
+
+    pub fn contains_source(&self, source: &Path) -> bool {
+        self.own_entries().any(|(key, _)| key == source) || self.included.contains_key(source)
+    }
+
+    /// Loads `<name>.local.toml` next to the main config `main`, if it exists:
+    /// its settings replace those of the main config and its entries are added,
+    /// winning over any other entry with the same source.
+    pub fn load_local(&mut self, main: &Path) -> Result<()> {
+        let mut name = main.file_stem().unwrap_or_default().to_os_string();
+        name.push(".local.toml");
+        let path = main.with_file_name(name);
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        let file = toml::from_str::<LocalConfig>(&contents).map_err(|source| Error::ConfigParse {
+            path: path.clone(),
+            source,
+        })?;
+
+        let shared = self.settings.clone();
+        self.settings.overlay(&file.settings, &file.settings);
+        for (source, entry) in file.entries {
+            self.included.insert(source, (path.clone(), entry));
+        }
+
+        self.local = Some(Local {
+            path,
+            settings: file.settings,
+            shared,
+        });
+        Ok(())
+    }
+
+    /// Loads the files listed in `include`, recursively, into `included`.
+    ///
+    /// `dir` is the directory the main config's include paths are relative to.
+    pub fn load_includes(&mut self, dir: &Path, main: &Path) -> Result<()> {
+        let mut loaded = HashSet::new();
+        let mut stack = vec![fs::canonicalize(main).unwrap_or_else(|_| main.to_path_buf())];
+        let include = self.include.clone();
+        self.load_include_list(&include, dir, &mut stack, &mut loaded)
+    }
+
+    fn load_include_list(
+        &mut self,
+        include: &[String],
+        dir: &Path,
+        stack: &mut Vec<PathBuf>,
+        loaded: &mut HashSet<PathBuf>,
+    ) -> Result<()> {
+        for pattern in include {
+            let pattern = dir.join(expand_path(Path::new(pattern))?);
+            let paths = glob(&pattern.to_string_lossy())
+                .map_err(|e| Error::InvalidConfig(format!("invalid include pattern {pattern:?}: {e}")))?;
+
+            let mut matched = false;
+            for path in paths {
+                matched = true;
+                let path = fs::canonicalize(path.map_err(|e| e.into_error())?)?;
+
+                if stack.contains(&path) {
+                    return Err(Error::IncludeCycle(stack.iter().chain([&path]).cloned().collect()));
+                }
+
+                // the same file reached through two includes is only read once
+                if !loaded.insert(path.clone()) {
+                    continue;
+                }
+
+                let contents = fs::read_to_string(&path)?;
+                let file = toml::from_str::<IncludedConfig>(&contents).map_err(|source| Error::ConfigParse {
+                    path: path.clone(),
+                    source,
+                })?;
+
+                for (source, entry) in file.entries {
+                    let previous = match self.included.get(&source) {
+                        Some((from, _)) => Some(from.clone()),
+                        None => self.entries.contains_key(&source).then(|| stack[0].clone()),
+                    };
+
+                    if let Some(previous) = previous {
+                        return Err(Error::DuplicateEntry {
+                            entry: source,
+                            first: format!("{previous:?}"),
+                            second: format!("{path:?}"),
+                        });
+                    }
+
+                    self.included.insert(source, (path.clone(), entry));
+                }
+
+                stack.push(path.clone());
+                let parent = path.parent().unwrap_or(Path::new("/")).to_path_buf();
+                self.load_include_list(&file.include, &parent, stack, loaded)?;
+                stack.pop();
+            }
+
+            if !matched {
+                eprintln!("{} include {pattern:?} matched no files", theme::warning("Warning:"));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn entries(&self) -> Result<impl Iterator<Item = ResolvedEntry>> {
+        Ok(self.entries_under(&self.get_root()?)?)
+    }
+
+    /// Like `entries`, but with sources resolved against `base`, which does not
+    /// have to exist (templated entries then expand to nothing).
+    pub fn entries_under(&self, base: &Path) -> io::Result<impl Iterator<Item = ResolvedEntry> + use<>> {
+        let mut entries = Vec::with_capacity(self.entries.len() + self.included.len());
+
+        for (source, entry) in self.all_entries() {
+            let resolved = Self::resolve_source(base, source)?;
+            let origin = self.origin(source);
+
+            for target in &entry.targets {
+                if template::is_template(target) {
+                    for (source, target) in template::expand(base, &resolved, target)? {
+                        entries.push(ResolvedEntry {
+                            source: base.join(&source),
+                            name: source,
+                            target,
+                            privileged: entry.privileged,
+                            enabled: entry.enabled,
+                            mode: entry.mode,
+                            owner: entry.owner.clone(),
+                            group: entry.group.clone(),
+                            link: entry.link.unwrap_or(self.link_kind()),
+                            folded: false,
+                            pre_link: entry.pre_link.clone(),
+                            post_link: entry.post_link.clone(),
+                            alias: entry.alias.clone(),
+                            origin: origin.clone(),
+                        });
+                    }
+                    continue;
+                }
+
+                if entry.dir_mode == Some(DirMode::Fold)
+                    && fs::symlink_metadata(&resolved).is_ok_and(|m| m.is_dir())
+                {
+                    for leaf in link::leaves(&resolved)? {
+                        entries.push(ResolvedEntry {
+                            name: source.clean().join(&leaf),
+                            source: resolved.join(&leaf),
+                            target: target.join(&leaf),
+                            privileged: entry.privileged,
+                            enabled: entry.enabled,
+                            mode: entry.mode,
+                            owner: entry.owner.clone(),
+                            group: entry.group.clone(),
+                            link: entry.link.unwrap_or(self.link_kind()),
+                            folded: true,
+                            pre_link: entry.pre_link.clone(),
+                            post_link: entry.post_link.clone(),
+                            alias: entry.alias.clone(),
+                            origin: origin.clone(),
+                        });
+                    }
+                    continue;
+                }
+
+                entries.push(ResolvedEntry {
+                    name: source.clean(),
+                    source: resolved.clone(),
+                    // targets are cleaned after expansion so `~user/..` survives until then
+                    target: target.clone(),
+                    privileged: entry.privileged,
+                    enabled: entry.enabled,
+                    mode: entry.mode,
+                    owner: entry.owner.clone(),
+                    group: entry.group.clone(),
+                    link: entry.link.unwrap_or(self.link_kind()),
+                    folded: false,
+                    pre_link: entry.pre_link.clone(),
+                    post_link: entry.post_link.clone(),
+                    alias: entry.alias.clone(),
+                    origin: origin.clone(),
+                });
+            }
+        }
+
+        Ok(entries.into_iter())
+    }
+}
+
+/// The config as `save_config` writes it, entries sorted by source.
+pub fn to_toml(cfg: &Config) -> String {
+    let Some(local) = &cfg.local else {
+        return toml::to_string_pretty(cfg).expect("failed to serialize config");
+    };
+
+    // the local settings stay out of the shared config
+    let mut shared = cfg.clone();
+    shared.settings.overlay(&local.settings, &local.shared);
+    toml::to_string_pretty(&shared).expect("failed to serialize config")
+}
+
+/// Writes `cfg` to `path` with `write_config`, then reads it back and checks
+/// it is the same config, so a bug in writing it can't leave a broken config
+/// behind: if it isn't, the previous contents are put back.
+pub fn save_config(cfg: &Config, path: &Path) -> Result<()> {
+    let previous = fs::read_to_string(path).ok();
+    let contents = to_toml(cfg);
+    write_config(path, contents.clone(), cfg.backups_kept())?;
+
+    let reason = match fs::read_to_string(path) {
+        Ok(written) => match toml::from_str::<Config>(&written) {
+            Ok(parsed) if toml::to_string_pretty(&parsed).ok().as_ref() == Some(&contents) => return Ok(()),
+            Ok(_) => "it parses into a different config".to_string(),
+            Err(e) => e.message().to_string(),
+        },
+        Err(e) => e.to_string(),
+    };
+
+    // straight back, a backup of the broken file would only crowd out the good ones
+    let restored = previous.is_some_and(|previous| write_config(path, previous, 0).is_ok());
+    Err(Error::RoundTrip {
+        path: path.to_path_buf(),
+        reason,
+        restored,
+    })
+}
+
+/// Replaces the config at `path` with `contents`, the way `save_config` does,
+/// after backing up what it replaces (see `backup`). `keep` backups are kept.
+pub fn write_config(path: &Path, contents: String, keep: usize) -> io::Result<()> {
+    backup::snapshot(path, &contents, keep);
+
+    // a config managed by dotlink itself is a symlink, replace the file it points to
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut tmp = path.clone().into_os_string();
+    tmp.push(".dotlink-tmp");
+    let tmp = PathBuf::from(tmp);
+
+    fs::write_synced(&tmp, contents)?;
+    if let Ok(metadata) = fs::metadata(&path) {
+        fs::set_permissions(&tmp, metadata.permissions())?;
+    }
+
+    fs::rename(&tmp, &path).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(toml: &str) -> Config {
+        let mut cfg: Config = toml::from_str(toml).unwrap();
+        // a variable of its own, so the environment of the test run can't interfere
+        cfg.root_env = Some("DOTLINK_TEST_ROOT_412".to_string());
+        cfg
+    }
+
+    /// `repo/.dotlink-root` holding `contents`, and the `repo/a/b` directory below it.
+    fn repo_with_marker(test: &str, contents: &str) -> PathBuf {
+        let repo = std::env::temp_dir().join(format!("dotlink-{}-{test}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&repo);
+        std::fs::create_dir_all(repo.join("a/b")).unwrap();
+        std::fs::write(repo.join(STOP_MARKER), contents).unwrap();
+        repo
+    }
+
+    #[test]
+    fn marker_found_above_the_working_directory() {
+        let repo = repo_with_marker("marker-above", "dotfiles\n");
+        let mut cfg = config("[settings]\n");
+
+        cfg.read_root_marker(&repo.join("a/b")).unwrap();
+        assert_eq!(cfg.configured_root().unwrap(), repo.join("dotfiles"));
+
+        std::fs::remove_dir_all(&repo).unwrap();
+    }
+
+    #[test]
+    fn config_value_wins_over_marker() {
+        let repo = repo_with_marker("marker-config", "dotfiles");
+        let mut cfg = config("[settings]\ndotlink_root = \"/srv/dotfiles\"\n");
+
+        cfg.read_root_marker(&repo).unwrap();
+        assert!(cfg.root_marker.is_none());
+        assert_eq!(cfg.configured_root().unwrap(), Path::new("/srv/dotfiles"));
+
+        std::fs::remove_dir_all(&repo).unwrap();
+    }
+
+    #[test]
+    fn marker_wins_over_environment_and_empty_marker_falls_back() {
+        // SAFETY: no other test reads or writes this variable
+        unsafe { std::env::set_var("DOTLINK_TEST_ROOT_412", "/from/env") };
+
+        let repo = repo_with_marker("marker-env", "$DOTLINK_TEST_ROOT_412/sub");
+        let mut cfg = config("[settings]\n");
+        cfg.read_root_marker(&repo).unwrap();
+        assert_eq!(cfg.configured_root().unwrap(), Path::new("/from/env/sub"));
+
+        // an empty marker stops the search without naming a root
+        std::fs::write(repo.join(STOP_MARKER), "\n").unwrap();
+        let mut cfg = config("[settings]\n");
+        cfg.read_root_marker(&repo.join("a")).unwrap();
+        assert!(cfg.root_marker.is_none());
+        assert_eq!(cfg.configured_root().unwrap(), Path::new("/from/env"));
+
+        std::fs::remove_dir_all(&repo).unwrap();
+    }
+
+    #[test]
+    fn symlinked_config_is_written_through() {
+        let dir =
+            std::env::temp_dir().join(format!("dotlink-{}-config-symlink", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("repo")).unwrap();
+        let real = dir.join("repo/Link.toml");
+        let link = dir.join("Link.toml");
+        std::fs::write(&real, "[settings]\n").unwrap();
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+
+        write_config(&link, "[settings]\ndotlink_root = \"/a\"\n".to_string(), 0).unwrap();
+        assert_eq!(std::fs::read_link(&link).unwrap(), real);
+        assert!(std::fs::read_to_string(&real).unwrap().contains("/a"));
+
+        // the round trip check of `save_config` reads through the link as well
+        let cfg = config("[settings]\ndotlink_root = \"/b\"\n");
+        save_config(&cfg, &link).unwrap();
+        assert_eq!(std::fs::read_link(&link).unwrap(), real);
+        assert!(std::fs::read_to_string(&real).unwrap().contains("/b"));
+        assert!(!dir.join("Link.toml.dotlink-tmp").exists());
+        assert!(!dir.join("repo/Link.toml.dotlink-tmp").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn relative_source_is_placed_under_the_root() {
+        let root = Path::new("/srv/dotfiles");
+        assert_eq!(
+            Config::resolve_source(root, Path::new("nvim/init.lua")).unwrap(),
+            root.join("nvim/init.lua")
+        );
+        assert_eq!(
+            Config::resolve_source(root, Path::new("./a/../vimrc")).unwrap(),
+            root.join("vimrc")
+        );
+    }
+
+    #[test]
+    fn home_source_is_not_placed_under_the_root() {
+        let home = PathBuf::from(std::env::var("HOME").unwrap());
+        assert_eq!(
+            Config::resolve_source(Path::new("/srv/dotfiles"), Path::new("~/x")).unwrap(),
+            home.join("x")
+        );
+    }
+}
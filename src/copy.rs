@@ -0,0 +1,153 @@
+use std::fs::Metadata;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use filetime::FileTime;
+
+use crate::theme;
+use crate::tracefs as fs;
+
+/// Moves a file or directory, falling back to copying and deleting the
+/// original when `from` and `to` are on different filesystems.
+pub fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            copy_tree(from, to, false)?;
+            if fs::symlink_metadata(from)?.is_dir() {
+                fs::remove_dir_all(from)
+            } else {
+                fs::remove_file(from)
+            }
+        }
+        result => result,
+    }
+}
+
+/// Copies a file or directory tree, following symlinks.
+pub fn copy_recursive(from: &Path, to: &Path) -> io::Result<()> {
+    copy_tree(from, to, true)
+}
+
+/// Copies a file, directory tree or symlink, preserving metadata as far as the
+/// platform and our privileges allow (see `copy_metadata`).
+fn copy_tree(from: &Path, to: &Path, follow: bool) -> io::Result<()> {
+    let metadata = if follow {
+        fs::metadata(from)?
+    } else {
+        fs::symlink_metadata(from)?
+    };
+
+    if metadata.file_type().is_symlink() {
+        fs::symlink(fs::read_link(from)?, to)?;
+    } else if metadata.is_dir() {
+        fs::create_dir(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_tree(&entry.path(), &to.join(entry.file_name()), follow)?;
+        }
+    } else {
+        fs::copy(from, to)?;
+    }
+
+    // last, so copying the children does not bump a directory's mtime again
+    copy_metadata(from, to, &metadata, follow)
+}
+
+/// Carries permissions, ownership, extended attributes and access/modification
+/// times over from `from` to `to`.
+///
+/// Ownership can usually only be changed by root, and extended attributes are
+/// not supported on every platform and filesystem; failures of those two are
+/// ignored.
+fn copy_metadata(from: &Path, to: &Path, metadata: &Metadata, follow: bool) -> io::Result<()> {
+    let is_symlink = metadata.file_type().is_symlink();
+
+    if !is_symlink {
+        fs::set_permissions(to, metadata.permissions())?;
+    }
+
+    let _ = fs::lchown(to, Some(metadata.uid()), Some(metadata.gid()));
+
+    if xattr::SUPPORTED_PLATFORM {
+        let names = if follow {
+            xattr::list_deref(from)
+        } else {
+            xattr::list(from)
+        };
+
+        for name in names.into_iter().flatten() {
+            let value = if follow {
+                xattr::get_deref(from, &name)
+            } else {
+                xattr::get(from, &name)
+            };
+
+            if let Ok(Some(value)) = value {
+                let _ = fs::set_xattr(to, &name, &value);
+            }
+        }
+    }
+
+    fs::set_symlink_file_times(
+        to,
+        FileTime::from_last_access_time(metadata),
+        FileTime::from_last_modification_time(metadata),
+    )
+}
+
+/// Replaces every symlink below `dir` with a copy of what it points to.
+///
+/// Dangling links and links to `dir` itself or one of its ancestors (which
+/// would copy forever) are left as they are, with a warning.
+pub fn dereference_tree(dir: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let metadata = fs::symlink_metadata(&path)?;
+
+        if metadata.is_dir() {
+            dereference_tree(&path)?;
+            continue;
+        }
+
+        if !metadata.file_type().is_symlink() {
+            continue;
+        }
+
+        let resolved = match fs::canonicalize(&path) {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                eprintln!(
+                    "  {} Keeping symlink {:?}, it cannot be resolved: {e}",
+                    theme::warning("Warning:"),
+                    path
+                );
+                continue;
+            }
+        };
+
+        if path.starts_with(&resolved) {
+            eprintln!(
+                "  {} Keeping symlink {:?}, it points to one of its own parents",
+                theme::warning("Warning:"),
+                path
+            );
+            continue;
+        }
+
+        println!(
+            "  - Dereferencing {} -> {}",
+            theme::path(format!("{:?}", path.display())),
+            theme::path(format!("{:?}", resolved.display()))
+        );
+
+        // copy next to the link first so a failed copy leaves the link intact
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".dotlink-tmp");
+        copy_recursive(&resolved, Path::new(&tmp))?;
+        fs::remove_file(&path)?;
+        fs::rename(&tmp, &path)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use path_clean::PathClean;
+
+use crate::config::Config;
+use crate::expand;
+use crate::link::DirMode;
+use crate::template;
+use crate::theme;
+use crate::tracefs as fs;
+
+/// A path going through the steps of its resolution, each printed as it is
+/// taken. The first step that fails ends it.
+struct Chain {
+    path: Option<PathBuf>,
+}
+
+impl Chain {
+    fn new(path: &Path) -> Self {
+        eprintln!("    {:<14} {:?}", "as written", path);
+        Chain {
+            path: Some(path.to_path_buf()),
+        }
+    }
+
+    fn step(&mut self, label: &str, f: impl FnOnce(&Path) -> io::Result<PathBuf>) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        match f(path) {
+            Ok(next) => {
+                eprintln!("    {label:<14} {:?}", next);
+                self.path = Some(next);
+            }
+            Err(e) => {
+                eprintln!("    {label:<14} {}", theme::error(format!("✖ {e}")));
+                self.path = None;
+            }
+        }
+    }
+
+    /// Where the path ends up on disk, which only changes it if a directory on
+    /// the way is a symlink. Not an error if it does not exist (yet).
+    fn on_disk(&self, of: impl FnOnce(&Path) -> io::Result<PathBuf>) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        match of(path) {
+            Ok(real) => eprintln!("    {:<14} {:?}", "on disk", real),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                eprintln!("    {:<14} (does not exist)", "on disk")
+            }
+            Err(e) => eprintln!("    {:<14} ({e})", "on disk"),
+        }
+    }
+}
+
+/// Prints every step from the sources and targets as written in the config to
+/// the paths `fix` works with, for `--dump-resolved-paths`. Read only, and
+/// missing files are not a problem.
+pub fn resolved_paths(cfg: &Config) {
+    let root = match cfg.configured_root() {
+        Ok(root) => {
+            eprintln!("{} {:?}", theme::info("root"), root);
+            Some(root)
+        }
+        Err(e) => {
+            eprintln!("{} {}", theme::info("root"), theme::error(format!("✖ {e}")));
+            None
+        }
+    };
+
+    for (source, entry) in cfg.all_entries() {
+        eprintln!();
+        match cfg.included.get(source) {
+            Some((file, _)) => {
+                eprintln!("{} (from {:?})", theme::path(format!("{source:?}")), file)
+            }
+            None => eprintln!("{}", theme::path(format!("{source:?}"))),
+        }
+
+        eprintln!("  source");
+        let mut chain = Chain::new(source);
+        chain.step("~ expanded", expand::expand_path_home);
+        chain.step("vars expanded", expand::expand_path_vars);
+        chain.step("in the root", |path| match &root {
+            Some(root) => Ok(root.join(path)),
+            None => Err(io::Error::other("no dotfiles root")),
+        });
+        chain.step("cleaned", |path| Ok(path.clean()));
+        chain.on_disk(|path| fs::canonicalize(path));
+        if entry.dir_mode == Some(DirMode::Fold) {
+            eprintln!("    (folded: a directory source is linked file by file below the target)");
+        }
+
+        for target in &entry.targets {
+            eprintln!("  target");
+            let mut chain = Chain::new(target);
+            if template::is_template(target) {
+                eprintln!("    (template: expanded once for every file the source matches)");
+                continue;
+            }
+
+            chain.step("built-ins", template::expand_builtins);
+            chain.step("~ expanded", expand::expand_path_home);
+            chain.step("vars expanded", expand::expand_path_vars);
+            if chain.path.as_ref().is_some_and(|path| path.is_relative()) {
+                chain.step("target base", |path| {
+                    let base = cfg
+                        .settings
+                        .target_base
+                        .as_deref()
+                        .unwrap_or(Path::new("~"));
+                    Ok(expand::expand_path(base)?.join(path))
+                });
+            }
+            chain.step("cleaned", |path| Ok(path.clean()));
+            // the link itself is not followed, only the directories leading to it
+            chain.on_disk(|path| {
+                let parent = fs::canonicalize(path.parent().unwrap_or(Path::new("/")))?;
+                Ok(parent.join(path.file_name().unwrap_or_default()))
+            });
+        }
+    }
+}
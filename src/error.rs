@@ -0,0 +1,156 @@
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::EXIT_CONFIG;
+use crate::EXIT_IO;
+use crate::expand::ExpandError;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Everything that can abort a command. `main` prints it and exits with `exit_code`.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("config not found at {}", not_found(.path, .alt.as_deref(), .var))]
+    ConfigNotFound {
+        path: PathBuf,
+        alt: Option<PathBuf>,
+        /// The environment variable that was checked for the root
+        var: String,
+    },
+
+    #[error("failed to parse {path:?}: {source}")]
+    ConfigParse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+
+    /// Anything else wrong with the config's contents
+    #[error("invalid config: {0}")]
+    InvalidConfig(String),
+
+    #[error("specify `settings.dotlink_root` in the config, a `.dotlink-root` file or the `{0}` environment variable, or use `--root-from-git`")]
+    MissingRoot(String),
+
+    #[error("cannot take the dotfiles root from {path:?}: {source}")]
+    RootMarker { path: PathBuf, source: io::Error },
+
+    #[error("cannot take the dotfiles root from git: {0}")]
+    GitRoot(String),
+
+    #[error("dotfiles root {0:?} does not exist")]
+    RootNotFound(PathBuf),
+
+    #[error("entry {entry:?} is defined in both {first} and {second}")]
+    DuplicateEntry {
+        entry: PathBuf,
+        first: String,
+        second: String,
+    },
+
+    #[error("include cycle: {}", cycle(.0))]
+    IncludeCycle(Vec<PathBuf>),
+
+    #[error("{path:?} overlaps the dotfiles root {root:?}, it cannot be moved into it")]
+    OverlapsRoot { path: PathBuf, root: PathBuf },
+
+    #[error("{0:?} already exists in the dotfiles root")]
+    Collision(PathBuf),
+
+    #[error("prefix {0:?} must be a relative path that stays inside the dotfiles root")]
+    InvalidPrefix(PathBuf),
+
+    #[error("invalid pattern {pattern:?}: {source}")]
+    InvalidPattern {
+        pattern: String,
+        source: glob::PatternError,
+    },
+
+    #[error("no entry matches {0:?}")]
+    NoMatchingEntry(String),
+
+    #[error("this command updates the config, which is not possible when it is read from stdin")]
+    StdinConfig,
+
+    #[error("invalid plan {path:?}: {reason}")]
+    InvalidPlan { path: PathBuf, reason: String },
+
+    /// A rewritten config that did not read back as what was written
+    #[error("the config written to {path:?} does not read back the same ({reason}), {}", put_back(*.restored))]
+    RoundTrip {
+        path: PathBuf,
+        reason: String,
+        restored: bool,
+    },
+
+    /// A path from the config or the command line that cannot be expanded
+    #[error(transparent)]
+    Expand(ExpandError),
+
+    #[error(transparent)]
+    Io(io::Error),
+}
+
+impl From<io::Error> for Error {
+    // expansion failures come through functions that return `io::Result`
+    fn from(e: io::Error) -> Self {
+        if e.get_ref().is_some_and(|inner| inner.is::<ExpandError>()) {
+            let inner = e.into_inner().expect("has an inner error");
+            return Error::Expand(*inner.downcast().expect("is an `ExpandError`"));
+        }
+
+        Error::Io(e)
+    }
+}
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io(_) => EXIT_IO,
+            _ => EXIT_CONFIG,
+        }
+    }
+}
+
+fn not_found(path: &Path, alt: Option<&Path>, var: &str) -> String {
+    match alt {
+        Some(alt) => format!("{path:?} or {alt:?}"),
+        None => format!("{path:?} and no `{var}` set"),
+    }
+}
+
+fn put_back(restored: bool) -> &'static str {
+    if restored {
+        "the previous version was put back"
+    } else {
+        "and the previous version could not be put back"
+    }
+}
+
+fn cycle(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|p| format!("{p:?}"))
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expand::expand_path;
+
+    #[test]
+    fn expansion_failures_are_not_io_errors() {
+        let err = Error::from(expand_path(Path::new("~nosuchuser-346/x")).unwrap_err());
+        assert!(matches!(err, Error::Expand(_)));
+        assert_eq!(err.exit_code(), EXIT_CONFIG);
+    }
+
+    #[test]
+    fn other_io_errors_stay_io_errors() {
+        let err = Error::from(io::Error::new(io::ErrorKind::InvalidInput, "bad input"));
+        assert!(matches!(err, Error::Io(_)));
+        assert_eq!(err.exit_code(), EXIT_IO);
+    }
+}
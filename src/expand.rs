@@ -0,0 +1,202 @@
+use std::env;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::tracefs as fs;
+
+/// Expands a path from the config or the command line.
+///
+/// Handles a leading `~` (current user) or `~user` (looked up in `/etc/passwd`),
+/// and `$VAR` / `${VAR}` / `%VAR%` environment variables anywhere in the path, where
+/// `$$` and `%%` stand for a literal `$` and `%`. Absolute paths without any of
+/// these pass through unchanged.
+pub fn expand_path(path: &Path) -> io::Result<PathBuf> {
+    let expanded = expand_vars(&expand_home(utf8(path)?)?)?;
+    Ok(PathBuf::from(expanded))
+}
+
+/// Only the `~` step of `expand_path`.
+pub fn expand_path_home(path: &Path) -> io::Result<PathBuf> {
+    Ok(PathBuf::from(expand_home(utf8(path)?)?))
+}
+
+/// Only the variables step of `expand_path`.
+pub fn expand_path_vars(path: &Path) -> io::Result<PathBuf> {
+    Ok(PathBuf::from(expand_vars(utf8(path)?)?))
+}
+
+/// `path` written so that `expand_path` gives it back as it is, for paths of
+/// existing files that go into the config: every `$` and `%` is doubled.
+pub fn escape(path: &Path) -> PathBuf {
+    match path.to_str() {
+        Some(raw) => PathBuf::from(raw.replace('$', "$$").replace('%', "%%")),
+        None => path.to_path_buf(),
+    }
+}
+
+fn utf8(path: &Path) -> io::Result<&str> {
+    path.to_str()
+        .ok_or_else(|| invalid(format!("path {path:?} is not valid UTF-8")))
+}
+
+fn expand_home(raw: &str) -> io::Result<String> {
+    let Some(rest) = raw.strip_prefix('~') else {
+        return Ok(raw.to_string());
+    };
+
+    let (user, tail) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let home = if user.is_empty() {
+        env::var("HOME").map_err(|_| invalid("cannot expand `~`: `HOME` is not set".into()))?
+    } else {
+        user_home(user)?
+    };
+
+    Ok(format!("{home}{tail}"))
+}
+
+fn user_home(user: &str) -> io::Result<String> {
+    let passwd = fs::read_to_string("/etc/passwd")?;
+
+    // name:password:uid:gid:gecos:home:shell
+    passwd
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() >= 6 && fields[0] == user)
+        .map(|fields| fields[5].to_string())
+        .ok_or_else(|| invalid(format!("cannot expand `~{user}`: no such user")))
+}
+
+fn expand_vars(raw: &str) -> io::Result<String> {
+    let mut out = String::with_capacity(raw.len());
+    let mut rest = raw;
+
+    while let Some(idx) = rest.find(['$', '%']) {
+        out.push_str(&rest[..idx]);
+        let sign = &rest[idx..idx + 1];
+        let windows = sign == "%";
+        rest = &rest[idx + 1..];
+
+        // `$$` and `%%` escape the sign itself
+        if let Some(after) = rest.strip_prefix(sign) {
+            out.push_str(sign);
+            rest = after;
+            continue;
+        }
+
+        // `%VAR%` as Windows writes it, any other `%` is kept as is
+        if windows {
+            let Some(end) = rest.find('%').filter(|end| is_name(&rest[..*end])) else {
+                out.push('%');
+                continue;
+            };
+            out.push_str(&var(&rest[..end], raw)?);
+            rest = &rest[end + 1..];
+            continue;
+        }
+
+        let (name, consumed) = if let Some(braced) = rest.strip_prefix('{') {
+            let end = braced
+                .find('}')
+                .ok_or_else(|| invalid(format!("unterminated `${{` in path {raw:?}")))?;
+            (&braced[..end], end + 2)
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            (&rest[..end], end)
+        };
+
+        // a lone `$` is kept as is
+        if name.is_empty() {
+            out.push('$');
+            continue;
+        }
+
+        out.push_str(&var(name, raw)?);
+        rest = &rest[consumed..];
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn is_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn var(name: &str, raw: &str) -> io::Result<String> {
+    env::var(name).map_err(|_| {
+        invalid(format!(
+            "environment variable `{name}` used in {raw:?} is not set"
+        ))
+    })
+}
+
+/// Why a path cannot be expanded: an unset variable, an unknown user or a
+/// path that is not UTF-8. It travels inside an `io::Error` of kind
+/// `InvalidInput` and becomes `Error::Expand` once converted to an `Error`.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct ExpandError(String);
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, ExpandError(msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_variables() {
+        // SAFETY: no other test reads or writes `APPDATA`
+        unsafe { env::set_var("APPDATA", r"C:\Users\me\AppData\Roaming") };
+        assert_eq!(
+            expand_vars(r"%APPDATA%\app").unwrap(),
+            r"C:\Users\me\AppData\Roaming\app"
+        );
+        assert_eq!(
+            expand_vars("%APPDATA%/$APPDATA").unwrap(),
+            r"C:\Users\me\AppData\Roaming/C:\Users\me\AppData\Roaming"
+        );
+    }
+
+    #[test]
+    fn undefined_windows_variable_is_an_error_like_unix_ones() {
+        let windows = expand_vars(r"%DOTLINK_SURELY_UNSET%\app").unwrap_err();
+        let unix = expand_vars("$DOTLINK_SURELY_UNSET/app").unwrap_err();
+        assert_eq!(windows.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(unix.kind(), io::ErrorKind::InvalidInput);
+        assert!(windows.to_string().contains("`DOTLINK_SURELY_UNSET`"));
+    }
+
+    #[test]
+    fn other_percent_signs_are_literal() {
+        assert_eq!(expand_vars("100%").unwrap(), "100%");
+        assert_eq!(expand_vars("50% of 10%.txt").unwrap(), "50% of 10%.txt");
+        assert_eq!(expand_vars("a%/b%").unwrap(), "a%/b%");
+    }
+
+    #[test]
+    fn doubled_signs_are_literal() {
+        assert_eq!(expand_vars("a$$b").unwrap(), "a$b");
+        assert_eq!(expand_vars("$${HOME}").unwrap(), "${HOME}");
+        assert_eq!(expand_vars("50%%off%%").unwrap(), "50%off%");
+        assert_eq!(expand_vars("%%").unwrap(), "%");
+        assert_eq!(expand_vars("$$$$").unwrap(), "$$");
+    }
+
+    #[test]
+    fn escaped_paths_expand_to_themselves() {
+        for raw in [
+            "/home/x/a$b",
+            "/home/x/50%off%",
+            "/home/x/${HOME}%PATH%",
+            "/home/x/$$",
+        ] {
+            let escaped = escape(Path::new(raw));
+            assert_eq!(expand_path(&escaped).unwrap(), Path::new(raw));
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::config::Config;
+use crate::config::Settings;
+use crate::error::Result;
+use crate::link::LinkKind;
+use crate::mode::Mode;
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum Format {
+    #[default]
+    Toml,
+    Json,
+    Yaml,
+}
+
+/// The config as `fix` sees it: includes and the active profile merged,
+/// templates expanded and paths resolved. Written by `config-export`.
+#[derive(Serialize)]
+struct Effective<'a> {
+    root: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target_base: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile: Option<&'a str>,
+    settings: &'a Settings,
+    entries: Vec<EffectiveEntry>,
+}
+
+#[derive(Serialize)]
+struct EffectiveEntry {
+    name: PathBuf,
+    source: PathBuf,
+    /// Resolved target, or the target as written if it cannot be resolved
+    target: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    privileged: bool,
+    enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mode: Option<Mode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    owner: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
+    link: LinkKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alias: Option<String>,
+}
+
+/// Renders the effective config of `cfg` in `format`.
+pub fn render(cfg: &Config, format: Format) -> Result<String> {
+    let mut entries = Vec::new();
+    for entry in cfg.entries()? {
+        let (target, error) = match cfg.resolve_target(&entry.target) {
+            Ok(target) => (target, None),
+            Err(e) => (entry.target, Some(e.to_string())),
+        };
+
+        entries.push(EffectiveEntry {
+            name: entry.name,
+            source: entry.source,
+            target,
+            error,
+            privileged: entry.privileged,
+            enabled: entry.enabled,
+            mode: entry.mode,
+            owner: entry.owner,
+            group: entry.group,
+            link: entry.link,
+            alias: entry.alias,
+        });
+    }
+
+    let effective = Effective {
+        root: cfg.get_root()?,
+        target_base: cfg.resolve_target(&PathBuf::new()).ok(),
+        profile: cfg.active_profile.as_deref(),
+        settings: &cfg.settings,
+        entries,
+    };
+
+    Ok(match format {
+        Format::Toml => toml::to_string_pretty(&effective).expect("failed to serialize config"),
+        Format::Json => {
+            serde_json::to_string_pretty(&effective).expect("failed to serialize config") + "\n"
+        }
+        Format::Yaml => {
+            let value = serde_json::to_value(&effective).expect("failed to serialize config");
+            let mut out = String::new();
+            yaml(&value, 0, &mut out);
+            out
+        }
+    })
+}
+
+/// Writes `value` (a map at the top level) as block-style YAML. Strings are
+/// double-quoted with JSON escapes, which YAML accepts as well.
+fn yaml(value: &Value, indent: usize, out: &mut String) {
+    let pad = "  ".repeat(indent);
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                out.push_str(&format!("{pad}{}:", Value::String(key.clone())));
+                yaml_nested(value, indent, out);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                out.push_str(&format!("{pad}-"));
+                yaml_nested(item, indent, out);
+            }
+        }
+        scalar => out.push_str(&format!("{pad}{scalar}\n")),
+    }
+}
+
+/// The value after a `key:` or `-`, on the same line if it is a scalar or empty.
+fn yaml_nested(value: &Value, indent: usize, out: &mut String) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            out.push('\n');
+            yaml(value, indent + 1, out);
+        }
+        Value::Array(items) if !items.is_empty() => {
+            out.push('\n');
+            yaml(value, indent + 1, out);
+        }
+        Value::Object(_) => out.push_str(" {}\n"),
+        Value::Array(_) => out.push_str(" []\n"),
+        scalar => out.push_str(&format!(" {scalar}\n")),
+    }
+}
@@ -0,0 +1,51 @@
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::theme;
+
+/// Errors of single entries that `--keep-going` carried on after.
+#[derive(Default)]
+pub struct Failures {
+    keep_going: bool,
+    failed: Vec<(PathBuf, Error)>,
+}
+
+impl Failures {
+    pub fn new(keep_going: bool) -> Self {
+        Self {
+            keep_going,
+            failed: Vec::new(),
+        }
+    }
+
+    /// Passes the error of the entry `name` on, or with `--keep-going` reports
+    /// and records it so the caller can continue with the next entry.
+    pub fn record(&mut self, name: &Path, result: Result<()>) -> Result<()> {
+        match result {
+            Err(e) if self.keep_going => {
+                eprintln!("{}", theme::error(format!("✖ Failed on {name:?}: {e}")));
+                self.failed.push((name.to_path_buf(), e));
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// Lists the recorded errors again, for the end of the output.
+    pub fn report(&self) {
+        if self.is_empty() {
+            return;
+        }
+
+        eprintln!("\n{} entries failed:", self.failed.len());
+        for (name, e) in &self.failed {
+            eprintln!("  - {name:?}: {e}");
+        }
+    }
+}
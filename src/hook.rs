@@ -0,0 +1,46 @@
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::tracefs;
+
+/// A `pre_link` or `post_link` command of an entry, run around creating or
+/// replacing one of its links.
+pub struct Hook {
+    /// `pre_link` or `post_link`
+    pub kind: &'static str,
+    pub command: String,
+    pub name: PathBuf,
+    pub source: PathBuf,
+    pub target: PathBuf,
+}
+
+impl Hook {
+    /// Runs the command through `sh -c` with the entry's paths in
+    /// `DOTLINK_NAME`, `DOTLINK_SOURCE` and `DOTLINK_TARGET`. A non-zero exit
+    /// status is an error.
+    pub fn run(&self) -> io::Result<()> {
+        let status = Command::new("sh")
+            .args(["-c", &self.command])
+            .env("DOTLINK_NAME", &self.name)
+            .env("DOTLINK_SOURCE", &self.source)
+            .env("DOTLINK_TARGET", &self.target)
+            .status()?;
+
+        let result = if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(status.to_string()))
+        };
+
+        tracefs::changed(
+            self.kind,
+            &[
+                ("command", &self.command),
+                ("source", &self.source),
+                ("target", &self.target),
+            ],
+            result,
+        )
+    }
+}
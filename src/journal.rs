@@ -0,0 +1,61 @@
+use std::path::PathBuf;
+
+use crate::copy;
+use crate::theme;
+use crate::tracefs as fs;
+
+/// A change to the filesystem that can be undone.
+enum Step {
+    Moved { from: PathBuf, to: PathBuf },
+    Linked(PathBuf),
+}
+
+/// The changes a batch made so far, for `add --transaction`, to put everything
+/// back when a later file fails.
+#[derive(Default)]
+pub struct Journal {
+    steps: Vec<Step>,
+}
+
+impl Journal {
+    pub fn moved(&mut self, from: PathBuf, to: PathBuf) {
+        self.steps.push(Step::Moved { from, to });
+    }
+
+    pub fn linked(&mut self, link: PathBuf) {
+        self.steps.push(Step::Linked(link));
+    }
+
+    /// Undoes every step, the last one first. A step that cannot be undone is
+    /// reported and the others are still tried. Returns whether all of them were.
+    pub fn roll_back(self) -> bool {
+        let mut all_ok = true;
+        for step in self.steps.into_iter().rev() {
+            let result = match &step {
+                Step::Linked(link) => {
+                    println!("  - Removing link {}", theme::path(format!("{:?}", link.display())));
+                    fs::remove_file(link)
+                }
+                Step::Moved { from, to } => {
+                    println!(
+                        "  - Moving {} -> {}",
+                        theme::path(format!("{:?}", to.display())),
+                        theme::path(format!("{:?}", from.display()))
+                    );
+                    copy::move_path(to, from)
+                }
+            };
+
+            if let Err(e) = result {
+                let path = match &step {
+                    Step::Linked(link) => link,
+                    Step::Moved { to, .. } => to,
+                };
+                eprintln!("{}", theme::error(format!("✖ Cannot undo the change to {path:?}: {e}")));
+                all_ok = false;
+            }
+        }
+
+        all_ok
+    }
+}
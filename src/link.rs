@@ -0,0 +1,193 @@
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::alias;
+use crate::tracefs as fs;
+
+/// How a target is connected to its source, `link` of an entry or
+/// `settings.default_link` in the config.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkKind {
+    #[default]
+    Symlink,
+    /// For tools that don't follow symlinks, files only and on the same filesystem
+    Hardlink,
+    /// A Finder alias, for macOS apps that want one, see `alias::SUPPORTED`
+    Alias,
+}
+
+impl LinkKind {
+    /// Creates a link of this kind at `target` for `source`.
+    pub fn create(self, source: &Path, target: &Path) -> io::Result<()> {
+        match self {
+            LinkKind::Symlink => fs::symlink(source, target),
+            LinkKind::Hardlink => fs::hard_link(source, target),
+            LinkKind::Alias => alias::create(source, target),
+        }
+    }
+
+    /// What a link of this kind is called in messages.
+    pub fn name(self) -> &'static str {
+        match self {
+            LinkKind::Symlink => "symlink",
+            LinkKind::Hardlink => "hard link",
+            LinkKind::Alias => "Finder alias",
+        }
+    }
+}
+
+/// How a directory source is linked, `dir_mode` of an entry.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DirMode {
+    /// One link for the whole directory
+    #[default]
+    Symlink,
+    /// Real directories at the target with a link for every file below the source
+    Fold,
+}
+
+impl DirMode {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "symlink" => Ok(DirMode::Symlink),
+            "fold" => Ok(DirMode::Fold),
+            _ => Err(format!("invalid dir_mode {s:?}, expected \"symlink\" or \"fold\"")),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DirMode::Symlink => "symlink",
+            DirMode::Fold => "fold",
+        }
+    }
+}
+
+/// Files (and symlinks) below `dir`, relative to it and sorted. Symlinked
+/// directories are not followed, they count as files.
+pub fn leaves(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut leaves = Vec::new();
+    collect_leaves(dir, Path::new(""), &mut leaves)?;
+    leaves.sort();
+    Ok(leaves)
+}
+
+fn collect_leaves(dir: &Path, rel: &Path, leaves: &mut Vec<PathBuf>) -> io::Result<()> {
+    for child in fs::read_dir(dir)? {
+        let child = child?;
+        let rel = rel.join(child.file_name());
+        if child.file_type()?.is_dir() {
+            collect_leaves(&child.path(), &rel, leaves)?;
+        } else {
+            leaves.push(rel);
+        }
+    }
+    Ok(())
+}
+
+/// Why `source` can't be hard linked at `target`, if it can't: directories
+/// can't be hard linked at all, files only within their filesystem.
+pub fn hardlink_unsupported(source: &Path, target: &Path) -> Option<String> {
+    let source_metadata = fs::metadata(source).ok()?;
+    if source_metadata.is_dir() {
+        return Some(format!(
+            "{source:?} is a directory, only files can be hard linked"
+        ));
+    }
+
+    // the target's parent may not exist yet, it will be on the filesystem of its closest ancestor
+    let dir = target
+        .ancestors()
+        .skip(1)
+        .find(|dir| fs::exists(dir).unwrap_or(false))?;
+    let dir_metadata = fs::metadata(dir).ok()?;
+    (dir_metadata.dev() != source_metadata.dev()).then(|| {
+        format!("{target:?} is on another filesystem than {source:?}, hard links can't cross filesystems")
+    })
+}
+
+/// Whether `a` and `b` are hard links to the same file (same device and inode).
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::symlink_metadata(a), fs::symlink_metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+/// Whether the filesystem of `dir` ignores case in names, like the default on
+/// macOS. Found out by looking up `dir`, or else one of its children, with the
+/// case of its name swapped, so nothing is written. `false` if no name has letters.
+pub fn case_insensitive(dir: &Path) -> bool {
+    let swapped = |path: &Path| {
+        let name = path.file_name()?.to_str()?;
+        let swapped: String = name
+            .chars()
+            .map(|c| match c {
+                c if c.is_lowercase() => c.to_uppercase().next().unwrap_or(c),
+                c => c.to_lowercase().next().unwrap_or(c),
+            })
+            .collect();
+        (swapped != name).then(|| path.with_file_name(swapped))
+    };
+
+    let probe = swapped(dir).map(|other| (dir.to_path_buf(), other)).or_else(|| {
+        fs::read_dir(dir)
+            .ok()?
+            .filter_map(|child| child.ok())
+            .find_map(|child| Some((child.path(), swapped(&child.path())?)))
+    });
+
+    probe.is_some_and(|(path, other)| same_file(&path, &other))
+}
+
+/// Whether `a` and `b` name the same file on a case-insensitive filesystem.
+pub fn same_ignoring_case(a: &Path, b: &Path) -> bool {
+    a.to_string_lossy().to_lowercase() == b.to_string_lossy().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `dir/source` holding "contents", with `dir/target` made by `make`.
+    fn source_and_target(test: &str, make: fn(&Path, &Path) -> io::Result<()>) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dotlink-{}-{test}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("source"), "contents").unwrap();
+        make(&dir.join("source"), &dir.join("target")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn hard_link_is_the_same_file() {
+        let dir = source_and_target("same-inode", |source, target| {
+            LinkKind::Hardlink.create(source, target)
+        });
+        assert!(same_file(&dir.join("source"), &dir.join("target")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn copy_with_the_same_contents_is_not_the_same_file() {
+        let dir = source_and_target("other-inode", |source, target| {
+            std::fs::copy(source, target).map(|_| ())
+        });
+        assert_eq!(std::fs::read(dir.join("target")).unwrap(), b"contents");
+        assert!(!same_file(&dir.join("source"), &dir.join("target")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_file_is_not_the_same_file() {
+        let dir = source_and_target("missing-inode", |_, _| Ok(()));
+        assert!(!same_file(&dir.join("source"), &dir.join("target")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,42 +1,282 @@
 use std::collections::HashMap;
 use std::collections::HashSet;
-use std::fs;
+use std::ffi::OsStr;
 use std::io;
+use std::io::Read;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Component;
 use std::path::Path;
 use std::path::PathBuf;
 use std::process::exit;
+use std::time::SystemTime;
 
-use clap::{Parser, Subcommand};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
 use colored::Colorize;
 use glob::glob;
 use path_clean::PathClean;
-use serde::Deserialize;
-use serde::Serialize;
+
+use config::CONFIG_VERSION;
+use config::Config;
+use config::Entry;
+use config::ResolvedEntry;
+use config::save_config;
+use error::Error;
+use error::Result;
+use expand::expand_path;
+use failures::Failures;
+use hook::Hook;
+use journal::Journal;
+use link::DirMode;
+use link::LinkKind;
+use mode::Mode;
+use privileged::Batch;
+use privileged::Op;
+use prompt::ConfirmEach;
+use repair::Change;
+use retry::Retry;
+use runlog::LogFile;
+use status::Record;
+use status::Report;
+use status::Resolved;
+use status::State;
+use status::StateName;
+use stats::Stats;
+use theme::ThemeName;
+use tracefs as fs;
+
+mod alias;
+mod backup;
+mod brace;
+mod canonical;
+mod caseless;
+mod changed;
+mod config;
+mod copy;
+mod dump;
+mod error;
+mod expand;
+mod export;
+mod failures;
+mod hook;
+mod journal;
+mod link;
+mod migrate;
+mod mode;
+mod owner;
+mod orphans;
+mod plan;
+mod preflight;
+mod privileged;
+mod prompt;
+mod repair;
+mod retry;
+mod runlog;
+mod since;
+mod stats;
+mod status;
+mod template;
+mod theme;
+mod tracefs;
+mod tree;
 
 const CFG_FILE_ENV_VAR: &str = "DOTLINK_ROOT";
 const CFG_FILE: &str = "Link.toml";
+const STOP_MARKER: &str = ".dotlink-root";
 
-fn main() -> io::Result<()> {
+// exit codes, documented in the README
+/// Links were checked and at least one mismatch, conflict or missing source was found
+const EXIT_ISSUES: i32 = 1;
+/// The config could not be found or parsed, a path could not be expanded, or the arguments were invalid
+const EXIT_CONFIG: i32 = 2;
+/// An IO error aborted the command
+const EXIT_IO: i32 = 3;
+
+fn main() {
     let cli = Cli::parse();
+
+    let result = run(cli);
+    runlog::finish(&match &result {
+        Ok(true) => "ok".to_string(),
+        Ok(false) => "issues found".to_string(),
+        Err(e) => format!("error: {e}"),
+    });
+
+    match result {
+        Ok(true) => {}
+        Ok(false) => exit(EXIT_ISSUES),
+        Err(e) => {
+            eprintln!("{} {e}", theme::error("Error:"));
+            exit(e.exit_code());
+        }
+    }
+}
+
+/// Runs the selected command, returns `false` if it found problems it could not fix.
+fn run(cli: Cli) -> Result<bool> {
+    if let Commands::Man = cli.commands {
+        man()?;
+        return Ok(true);
+    }
+
+    if let Commands::ConfigSchema = cli.commands {
+        print!("{}", config::SCHEMA);
+        return Ok(true);
+    }
+
+    if cli.trace {
+        fs::enable();
+    }
+
     let cfg_path = get_cfg_path(&cli)?;
+
+    if is_stdin(&cfg_path) && matches!(
+            cli.commands,
+            Commands::Add(_)
+                | Commands::Unlink(_)
+                | Commands::Relocate { .. }
+                | Commands::RepairConfig { .. }
+                | Commands::CheckOrphans { adopt: true, .. }
+                | Commands::Fmt { .. }
+                | Commands::Migrate
+                | Commands::ConfigBackups
+                | Commands::ConfigRestore { .. }
+        )
+    {
+        return Err(Error::StdinConfig);
+    }
+
+    // these only need the file, so a config that no longer loads can be rolled back
+    match &cli.commands {
+        Commands::ConfigBackups => return config_backups(&cfg_path),
+        Commands::ConfigRestore { id } => return config_restore(&cfg_path, id),
+        // `-` for stdin, as given
+        Commands::ConfigPath if is_stdin(&cfg_path) => {
+            println!("-");
+            return Ok(true);
+        }
+        Commands::ConfigPath => {
+            println!("{}", std::path::absolute(&cfg_path)?.clean().display());
+            return Ok(true);
+        }
+        _ => {}
+    }
+
     let mut cfg = load_cfg(&cfg_path)?;
+    let parents = !cli.no_parents;
 
-    match cli.commands {
-        Commands::Fix => fix(&cfg)?,
-        Commands::Add { targets, root } => add(cfg_path, &mut cfg, &targets, root)?,
-        Commands::Unlink { entries } => unlink(cfg_path, &mut cfg, &entries)?,
+    let colors = cfg.settings.colors.clone().unwrap_or_default();
+    theme::init(cli.theme, &colors).map_err(Error::InvalidConfig)?;
+
+    let log_file = match cli.log_file {
+        Some(Some(path)) => LogFile::Path(path),
+        Some(None) => LogFile::Enabled(true),
+        None => cfg.settings.log_file.clone().unwrap_or(LogFile::Enabled(false)),
+    };
+    // the run is more important than its log
+    if let Err(e) = runlog::start(&log_file) {
+        eprintln!("  {} Cannot write the run log: {e}", theme::warning("Warning:"));
+    }
+    cfg.select_profile(cli.profile.as_deref())?;
+    cfg.check_aliases()?;
+    cfg.check_links()?;
+    cfg.root_env = cli.root_env.clone();
+    cfg.read_root_marker(&std::env::current_dir()?)?;
+    if cli.root_from_git || cfg.settings.root_from_git {
+        cfg.infer_git_root()?;
+    }
+    if cli.dump_resolved_paths {
+        dump::resolved_paths(&cfg);
     }
 
-    Ok(())
+    match cli.commands {
+        Commands::Fix(args) => fix(&cfg, &args, parents),
+        Commands::Add(args) => add(cfg_path, &mut cfg, &args, parents),
+        Commands::Unlink(args) => {
+            let paths = if args.stdin { read_stdin_paths()? } else { Vec::new() };
+            unlink(cfg_path, &mut cfg, &args, &paths, parents)
+        }
+        Commands::Check => check(&cfg),
+        Commands::Status { stats: true, json, .. } => disk_usage(&cfg, json),
+        Commands::Status {
+            count,
+            json_lines,
+            report_file,
+            filter_state,
+            explain,
+            ..
+        } => status(&cfg, count, json_lines, report_file.as_deref(), &filter_state, explain),
+        Commands::Resolve { entry, json } => resolve(&cfg, &entry, json),
+        Commands::Tree { json } => tree(&cfg, json),
+        Commands::Relocate { new_root, yes } => relocate(&cfg_path, &mut cfg, &new_root, yes),
+        Commands::RepairConfig { yes } => repair_config(&cfg_path, &mut cfg, yes),
+        Commands::CheckOrphans {
+            dirs,
+            max_depth,
+            adopt,
+        } => check_orphans(&cfg_path, &mut cfg, &dirs, max_depth, adopt),
+        Commands::Fmt { check } => format_config(&cfg_path, &mut cfg, check),
+        Commands::Migrate => migrate_config(&cfg_path, &cfg),
+        Commands::ConfigExport { format } => {
+            print!("{}", export::render(&cfg, format)?);
+            Ok(true)
+        }
+        Commands::Plan { repoint } => print_plan(&cfg, repoint),
+        Commands::ApplyFrom { plan, skip_drifted } => apply_from(&cfg, &plan, skip_drifted, parents),
+        Commands::Root => {
+            println!("{}", cfg.get_root()?.display());
+            Ok(true)
+        }
+        Commands::Man
+        | Commands::ConfigSchema
+        | Commands::ConfigBackups
+        | Commands::ConfigRestore { .. }
+        | Commands::ConfigPath => unreachable!("handled before loading the config"),
+    }
 }
 
 #[derive(Parser)]
-#[command(long_about = None)]
+#[command(version, long_about = None)]
 struct Cli {
-    /// Config path (finds one in the current directory if not specified)
+    /// Config path (searched for in the current directory and its parents if not specified, `-` reads it from stdin)
     #[clap(short)]
     config: Option<PathBuf>,
 
+    /// How many parent directories to search for the config when it is not in the current one
+    #[clap(long, global = true, value_name = "N")]
+    max_config_depth: Option<usize>,
+
+    /// Fail instead of creating missing parent directories of link targets
+    #[clap(long, global = true)]
+    no_parents: bool,
+
+    /// Color theme for the output, `NO_COLOR` disables colors regardless
+    #[clap(long, global = true, value_enum, default_value_t)]
+    theme: ThemeName,
+
+    /// Profile whose entries are merged over the base entries (default: `settings.default_profile`)
+    #[clap(long, global = true)]
+    profile: Option<String>,
+
+    /// Log every filesystem operation with its result to stderr
+    #[clap(long, global = true)]
+    trace: bool,
+
+    /// Without a root in the config or `DOTLINK_ROOT`, use the top level of the git checkout around the working directory
+    #[clap(long, global = true)]
+    root_from_git: bool,
+
+    /// Environment variable that names the root, instead of `DOTLINK_ROOT`
+    #[clap(long, global = true, value_name = "NAME")]
+    root_env: Option<String>,
+
+    /// Print how the source and targets of every entry are expanded, step by step, before running the command
+    #[clap(long, global = true)]
+    dump_resolved_paths: bool,
+
+    /// Append a record of this run and its changes to PATH (`~/.local/state/dotlink/log` if not given)
+    #[clap(long, global = true, value_name = "PATH", num_args = 0..=1, require_equals = true)]
+    log_file: Option<Option<PathBuf>>,
+
     #[command(subcommand)]
     commands: Commands,
 }
@@ -44,133 +284,646 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Create missing entries and validates existing ones
-    Fix,
+    Fix(FixArgs),
 
     /// Unlink entries
-    Unlink { entries: Vec<String> },
+    Unlink(UnlinkArgs),
+
+    /// Add the specified file or directory to the dotfiles root
+    Add(AddArgs),
+
+    /// Validate the config and sources without looking at link targets (for git hooks)
+    Check,
+
+    /// Show the state of every entry without changing anything
+    Status {
+        /// Only print the number of entries in each state
+        #[clap(long)]
+        count: bool,
+
+        /// Print one JSON object per entry as it is checked, and a final summary object
+        #[clap(long)]
+        json_lines: bool,
+
+        /// Also write the full report as JSON to this file
+        #[clap(long, value_name = "PATH")]
+        report_file: Option<PathBuf>,
+
+        /// Only list entries in these states (comma-separated or repeated)
+        #[clap(long, value_enum, value_delimiter = ',', value_name = "STATE", conflicts_with = "count")]
+        filter_state: Vec<StateName>,
+
+        /// Add to every entry which part of the config it comes from, or why it is skipped
+        #[clap(long, conflicts_with_all = ["count", "json_lines"])]
+        explain: bool,
+
+        /// Show how much disk space the sources take instead, per entry and in total
+        #[clap(long, conflicts_with_all = ["count", "json_lines", "report_file", "filter_state", "explain"])]
+        stats: bool,
+
+        /// Print the `--stats` report as JSON
+        #[clap(long, requires = "stats")]
+        json: bool,
+    },
+
+    /// Show the resolved source, target and state of the entry with this name, source or target
+    Resolve {
+        entry: String,
+
+        /// Print one JSON object per link instead
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Show the managed links as a tree grouped by target directory, with their state
+    Tree {
+        /// Print the tree as nested JSON objects instead
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Point the config at a dotfiles root that was moved, updating entries and links
+    Relocate {
+        new_root: PathBuf,
 
-    /// Add the specified file or directory to dotfiles_root
-    Add {
-        targets: Vec<String>,
-        /// Use a custom root, uses DOTLINK_ROOT env variable if not specified
+        /// Don't ask for confirmation
+        #[clap(long, short)]
+        yes: bool,
+    },
+
+    /// Propose config updates for sources and links that no longer match it
+    RepairConfig {
+        /// Apply the changes without asking
+        #[clap(long, short)]
+        yes: bool,
+    },
+
+    /// List symlinks into the dotfiles root that no entry manages
+    CheckOrphans {
+        /// Directories to search, the home directory if none are given
+        dirs: Vec<PathBuf>,
+
+        /// How many levels of subdirectories to search below each directory
+        #[clap(long, value_name = "N", default_value_t = 4)]
+        max_depth: usize,
+
+        /// Add entries for the links found to the config
+        #[clap(long)]
+        adopt: bool,
+    },
+
+    /// Upgrade the config to the current format, keeping its comments
+    Migrate,
+
+    /// Rewrite the config in a canonical form: entries sorted, paths cleaned, home as `~`
+    #[command(visible_alias = "format-config")]
+    Fmt {
+        /// Don't write anything, print a diff and fail if the config is not canonical
+        #[clap(long)]
+        check: bool,
+    },
+
+    /// Print an example config documenting every supported key
+    ConfigSchema,
+
+    /// Print the config in effect: includes and the profile merged, templates expanded, paths resolved
+    ConfigExport {
+        #[clap(long, value_enum, default_value_t)]
+        format: export::Format,
+    },
+
+    /// List the backups of the config taken before dotlink rewrote it
+    ConfigBackups,
+
+    /// Put a backup of the config back in place, links and files are not touched
+    ConfigRestore {
+        /// Backup to restore, as listed by `config-backups`
+        id: String,
+    },
+
+    /// Print the links fix would make as a JSON plan, for `apply-from`
+    Plan {
+        /// Also plan to replace symlinks that point somewhere other than their source
+        #[clap(long)]
+        repoint: bool,
+    },
+
+    /// Make the links of a plan written by `plan`, if the files are still as it expects
+    ApplyFrom {
+        /// The plan, `-` for stdin
+        plan: PathBuf,
+
+        /// Leave out actions whose files changed since the plan was made, instead of refusing the whole plan
         #[clap(long)]
-        root: Option<PathBuf>,
+        skip_drifted: bool,
     },
+
+    /// Print the path of the config file dotlink uses
+    ConfigPath,
+
+    /// Print the dotfiles root dotlink uses
+    Root,
+
+    /// Print a man page for dotlink to stdout
+    #[command(hide = true)]
+    Man,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Config {
-    settings: Settings,
-    #[serde(default)]
-    entries: HashMap<PathBuf, PathBuf>,
+#[derive(Args)]
+struct UnlinkArgs {
+    entries: Vec<String>,
+
+    /// Also read paths to unlink from stdin, separated by newlines or NUL bytes
+    #[clap(long)]
+    stdin: bool,
+
+    /// Report errors of single entries and carry on with the rest instead of stopping
+    #[clap(long)]
+    keep_going: bool,
+
+    /// Match aliases and patterns regardless of case, against the paths of entries
+    #[clap(long, short)]
+    ignore_case: bool,
+
+    /// Remove the directories around removed links that are left empty, up to the home directory or `settings.target_base`
+    #[clap(long)]
+    delete_empty_dirs: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Settings {
-    dotlink_root: Option<PathBuf>,
+#[derive(Args)]
+struct AddArgs {
+    targets: Vec<String>,
+
+    /// Use a custom root, uses DOTLINK_ROOT env variable if not specified
+    #[clap(long)]
+    root: Option<PathBuf>,
+
+    /// Replace symlinks inside added directories with copies of what they point to
+    #[clap(long, visible_alias = "dereference")]
+    follow_symlinks: bool,
+
+    /// For directories, pick which of their top-level entries to add instead of the whole directory
+    #[clap(long, short)]
+    interactive: bool,
+
+    /// Log files that fail to be added and carry on with the rest
+    #[clap(long)]
+    ignore_conflicts: bool,
+
+    /// Leave files and directories starting with `.` inside added directories where they are
+    #[clap(long)]
+    no_hidden: bool,
+
+    /// What to do when the dotfiles root already has a file with the same name
+    #[clap(long, value_enum, value_name = "STRATEGY", default_value_t)]
+    on_collision: OnCollision,
+
+    /// Link the files back with hard links instead of `settings.default_link`
+    #[clap(long)]
+    hardlink: bool,
+
+    /// Record the targets relative to this directory, which the files have to be in
+    #[clap(long, value_name = "DIR")]
+    target_base: Option<PathBuf>,
+
+    /// Store the files in this directory of the dotfiles root, e.g. `bash`
+    #[clap(long, value_name = "DIR")]
+    prefix: Option<PathBuf>,
+
+    /// Only show where each file would go and whether its name is taken, without changing anything
+    #[clap(long)]
+    preview: bool,
+
+    /// Record symlinks that already point into the dotfiles root as they are, without moving or linking anything
+    #[clap(
+        long,
+        visible_alias = "adopt-existing",
+        conflicts_with_all = ["follow_symlinks", "interactive", "on_collision", "hardlink", "prefix"]
+    )]
+    no_move: bool,
+
+    /// Add all files or none: when one fails, put back the ones added before it
+    #[clap(long, conflicts_with_all = ["ignore_conflicts", "follow_symlinks", "preview"])]
+    transaction: bool,
 }
 
-impl Config {
-    fn get_root(&self) -> io::Result<PathBuf> {
-        fs::canonicalize(
-            &self
-                .settings
-                .dotlink_root
-                .clone()
-                .unwrap_or_else(|| PathBuf::from(
-                    std::env::var(CFG_FILE_ENV_VAR).expect(
-                        &format!("specify `dotfiles_root` in configuration file or `{CFG_FILE_ENV_VAR}` environment variable."))
-                    )
-                ),
-        )
-    }
+#[derive(Args)]
+struct FixArgs {
+    /// Replace symlinks that point somewhere other than their source
+    #[clap(long)]
+    repoint: bool,
+
+    /// Replace symlinks whose destination does not exist, without touching other mismatches
+    #[clap(long)]
+    clobber_broken: bool,
+
+    /// Only report what would be changed
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Don't list entries that are already linked or disabled, only count them
+    #[clap(long, short, visible_alias = "only-missing")]
+    quiet: bool,
+
+    /// Only process entries whose source was modified after this point
+    /// (a duration like `10m` or `2h`, `@<unix seconds>`, or `YYYY-MM-DD[THH:MM[:SS]]` in UTC)
+    #[clap(long, value_parser = since::parse_since)]
+    since: Option<SystemTime>,
+
+    /// Only process entries whose source git reports as changed since this commit, e.g. `ORIG_HEAD`
+    #[clap(long, value_name = "REF")]
+    since_commit: Option<String>,
+
+    /// Only process entries whose target is below this directory, can be given several times
+    #[clap(long, value_name = "DIR")]
+    only_dir: Vec<PathBuf>,
+
+    /// Permissions to give every file of the sources, e.g. `0600` (an entry's `mode` wins)
+    #[clap(long, value_name = "MODE", value_parser = Mode::parse)]
+    chmod_files: Option<Mode>,
+
+    /// Permissions to give every directory of the sources, e.g. `0700` (an entry's `mode` wins)
+    #[clap(long, value_name = "MODE", value_parser = Mode::parse)]
+    chmod_dirs: Option<Mode>,
+
+    /// Write a JSON report of the state of every entry after the run to this file
+    #[clap(long, value_name = "PATH")]
+    report_file: Option<PathBuf>,
+
+    /// Whether a source that is a symlink itself is linked to as it is, or to where it leads
+    #[clap(long, value_enum, value_name = "MODE", default_value_t)]
+    source_symlinks: SourceSymlinks,
+
+    /// For mismatched links, also show the raw link and both paths fully resolved
+    #[clap(long, short, visible_alias = "verbose-diff")]
+    verbose: bool,
+
+    /// What to do when a directory above a target is a symlink, which places the link elsewhere
+    #[clap(long, value_enum, value_name = "MODE", default_value_t)]
+    dereference_target: DereferenceTarget,
+
+    /// Ask before creating or replacing each link (`a` answers yes to all the rest)
+    #[clap(long, conflicts_with = "dry_run")]
+    confirm_each: bool,
+
+    /// Report errors of single entries and carry on with the rest instead of stopping
+    #[clap(long)]
+    keep_going: bool,
+
+    /// Don't run the `pre_link` and `post_link` commands of entries
+    #[clap(long)]
+    no_hooks: bool,
+
+    /// What to do with a file or directory in the way of a link
+    #[clap(long, value_enum, value_name = "STRATEGY", default_value_t)]
+    resolve_conflicts: ResolveConflicts,
+
+    /// Read back every link made in this run and report those that don't point to their source
+    #[clap(long, conflicts_with = "dry_run")]
+    verify: bool,
+
+    /// Before every entry, print which part of the config it comes from, or why it is skipped
+    #[clap(long, conflicts_with = "quiet")]
+    explain: bool,
 
-    fn entries(&self) -> io::Result<impl Iterator<Item = (PathBuf, PathBuf, PathBuf)>> {
-        let base = self.get_root()?;
-        Ok(self.entries.iter().map(move |(source, target)| {
-            (source.clean(), base.join(source.clean()), target.clean())
-        }))
+    /// Fail if any entry was not already linked correctly, including missing links (for CI)
+    #[clap(long)]
+    strict: bool,
+}
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+enum DereferenceTarget {
+    /// Warn and link through the symlinked directory
+    #[default]
+    Warn,
+    /// Link at the resolved location instead
+    Resolve,
+    /// Report the entry as a problem and leave it alone
+    Refuse,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, ValueEnum)]
+enum ResolveConflicts {
+    /// Report the conflict and leave everything as it is
+    #[default]
+    Report,
+    /// Move the existing file aside to `<name>.bak` and link in its place
+    Theirs,
+    /// Make the existing file the new source (the old one is kept as `<name>.bak`) and link
+    Ours,
+    /// Move the existing file aside to `<name>.bak` without linking
+    Backup,
+}
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+enum SourceSymlinks {
+    /// Link to the source symlink, making a chain of links
+    #[default]
+    Keep,
+    /// Link to the final destination of the source symlink
+    Resolve,
+}
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+enum OnCollision {
+    /// Leave the file alone and carry on
+    #[default]
+    Skip,
+    /// Store it under a free name like `name-2`
+    Rename,
+    /// Fail, like any other error (see `--ignore-conflicts`)
+    Error,
+}
+
+/// Makes sure the parent directory of `path` exists, creating it only if `create` is set.
+/// Directories it creates get `dir_perms`, if set.
+fn ensure_parent(path: &Path, create: bool, dir_perms: Option<Mode>) -> io::Result<()> {
+    let Some(parent) = path.parent() else {
+        return Ok(());
+    };
+
+    if create {
+        mode::create_dir_all(parent, dir_perms)
+    } else if parent.is_dir() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("parent directory {parent:?} does not exist (--no-parents is set)"),
+        ))
     }
 }
 
+/// Renders the man page for the whole CLI followed by one page per subcommand.
+fn man() -> io::Result<()> {
+    let cmd = Cli::command();
+    let mut out = io::stdout().lock();
+
+    clap_mangen::Man::new(cmd.clone()).render(&mut out)?;
+    for sub in cmd.get_subcommands().filter(|sub| !sub.is_hide_set()) {
+        let name = format!("{}-{}", cmd.get_name(), sub.get_name());
+        clap_mangen::Man::new(sub.clone()).title(name).render(&mut out)?;
+    }
+
+    Ok(())
+}
 
-fn expand_tilde(path: &PathBuf) -> String {
-    path.to_str()
-        .unwrap()
-        .to_string()
-        .replace("~", &std::env::var("HOME").expect("Cannot expand ~"))
+/// `-c -` reads the config from stdin instead of a file.
+fn is_stdin(cfg_path: &Path) -> bool {
+    cfg_path == Path::new("-")
 }
 
-fn get_cfg_path(cli: &Cli) -> io::Result<PathBuf> {
+fn get_cfg_path(cli: &Cli) -> Result<PathBuf> {
+    if cli.config.as_deref().is_some_and(is_stdin) {
+        return Ok(PathBuf::from("-"));
+    }
+
     let cwd = std::env::current_dir()?;
-    let mut cfg_path = cli.config.clone().unwrap_or(cwd.join(CFG_FILE));
+    let mut cfg_path = match &cli.config {
+        Some(path) => path.clone(),
+        None => find_cfg(&cwd, cli.max_config_depth).unwrap_or(cwd.join(CFG_FILE)),
+    };
 
     if !fs::exists(&cfg_path)? {
-        if let Ok(var) = std::env::var(CFG_FILE_ENV_VAR) {
+        let root_env = cli.root_env.as_deref().unwrap_or(CFG_FILE_ENV_VAR);
+        if let Ok(var) = std::env::var(root_env) {
             let alt = PathBuf::from(var).join(CFG_FILE);
-            if fs::exists(&alt)? {
-                cfg_path = alt;
-            } else {
-                eprintln!("Config not found at {cfg_path:?} or {alt:?}");
-                exit(1);
+            if !fs::exists(&alt)? {
+                return Err(Error::ConfigNotFound {
+                    path: cfg_path,
+                    alt: Some(alt),
+                    var: root_env.to_string(),
+                });
             }
+            cfg_path = alt;
         } else {
-            eprintln!("Config not found at {cfg_path:?} and no {CFG_FILE_ENV_VAR} set.");
-            exit(1);
+            return Err(Error::ConfigNotFound {
+                path: cfg_path,
+                alt: None,
+                var: root_env.to_string(),
+            });
         }
     }
 
     Ok(cfg_path)
 }
 
-fn load_cfg(cfg_path: &Path) -> Result<Config, io::Error> {
-    let cfg_contents = fs::read_to_string(cfg_path)?;
-    let cfg = toml::from_str::<Config>(&cfg_contents).unwrap_or_else(|e| {
-        eprintln!("Failed to parse config file: {e}");
-        exit(0);
-    });
+/// Looks for the config in `dir` and its parents, up to `max_depth` levels up.
+///
+/// A directory containing a `.dotlink-root` marker is the last one searched, so
+/// a config further up never gets picked up from inside a nested layout.
+fn find_cfg(dir: &Path, max_depth: Option<usize>) -> Option<PathBuf> {
+    for (depth, dir) in dir.ancestors().enumerate() {
+        if max_depth.is_some_and(|max| depth > max) {
+            break;
+        }
+
+        let candidate = dir.join(CFG_FILE);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+
+        if dir.join(STOP_MARKER).exists() {
+            break;
+        }
+    }
+
+    None
+}
+
+fn load_cfg(cfg_path: &Path) -> Result<Config> {
+    let cfg_contents = if is_stdin(cfg_path) {
+        io::read_to_string(io::stdin())?
+    } else {
+        fs::read_to_string(cfg_path)?
+    };
+    migrate::check_version(&cfg_contents)?;
+    let mut cfg = toml::from_str::<Config>(&cfg_contents).map_err(|source| Error::ConfigParse {
+        path: cfg_path.to_path_buf(),
+        source,
+    })?;
+
+    // includes of a config read from stdin are relative to the working directory
+    let dir = match cfg_path.parent() {
+        Some(parent) if !is_stdin(cfg_path) => parent.to_path_buf(),
+        _ => std::env::current_dir()?,
+    };
+
+    cfg.load_includes(&dir, cfg_path)?;
+    if !is_stdin(cfg_path) {
+        cfg.load_local(cfg_path)?;
+    }
 
     Ok(cfg)
 }
 
+/// Prepares `cfg` for targets recorded relative to `base` (canonical): sets
+/// `settings.target_base` if neither it nor relative targets depending on its
+/// default exist yet, or warns if it points elsewhere.
+fn use_target_base(cfg: &mut Config, base: &Path) {
+    let has_relative = cfg.all_entries().flat_map(|(_, entry)| &entry.targets).any(|target| {
+        !target.is_absolute() && !target.to_string_lossy().starts_with(['~', '$'])
+    });
+
+    if cfg.settings.target_base.is_none() && !has_relative {
+        let portable = cfg.portable_target(base);
+        println!(
+            "[{}] Setting `settings.target_base` to {:?}",
+            theme::info("INFO"),
+            portable
+        );
+        cfg.settings.target_base = Some(portable);
+        return;
+    }
+
+    let resolved = cfg.resolve_target(Path::new("")).and_then(fs::canonicalize);
+    if !resolved.is_ok_and(|resolved| resolved == base) {
+        eprintln!(
+            "  {} The target base is {:?}, so fix places the added targets there instead of under {:?}",
+            theme::warning("Warning:"),
+            cfg.settings.target_base.as_deref().unwrap_or(Path::new("~")),
+            base
+        );
+    }
+}
+
+/// Moves `target` into `root`, links it back and records it in `cfg`, which
+/// the caller saves. The changes are logged to `journal`, if given.
 fn add_one(
     cfg: &mut Config,
-    cfg_path: &PathBuf,
     target: PathBuf,
     root: &Path,
-) -> io::Result<()> {
+    target_base: Option<&Path>,
+    args: &AddArgs,
+    parents: bool,
+    mut journal: Option<&mut Journal>,
+) -> Result<bool> {
+    if args.no_move {
+        return adopt_link(cfg, &target, root, target_base, args.preview);
+    }
+
     if !target.exists() {
         eprintln!("Target: {:?} does not exist", target);
-        return Ok(());
+        return Ok(false);
     }
 
     let target = target.canonicalize()?.clean();
-    let name = target.file_name().unwrap_or_else(|| {
-        eprintln!("Fatal: Could not determine filename for target {:?}", target);
-        exit(1);
-    });
+    let Some(name) = target.file_name() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("could not determine filename for target {target:?}"),
+        )
+        .into());
+    };
 
-    let dest_in_root = root.join(name);
+    if overlaps_root(&target, root) {
+        return Err(Error::OverlapsRoot {
+            path: target,
+            root: root.to_path_buf(),
+        });
+    }
 
-    // check if an entry with the same destination path already exists
-    if cfg.entries.contains_key(&dest_in_root) {
-        eprintln!(
-            "Target entry for {:?} already exists in config.",
-            dest_in_root
+    let Some(recorded) = recorded_target(cfg, &target, target_base) else {
+        return Ok(false);
+    };
+
+    let dir_in_root = match &args.prefix {
+        Some(prefix) => root.join(prefix),
+        None => root.to_path_buf(),
+    };
+    let mut dest_in_root = dir_in_root.join(name);
+
+    // an entry or an unmanaged file already using that name, in any case where
+    // the filesystem would store both as one file
+    let case_insensitive = link::case_insensitive(root);
+    let taken = |dest: &Path| {
+        cfg.contains_source(dest)
+            || fs::symlink_metadata(dest).is_ok()
+            || case_insensitive
+                && cfg.all_entries().any(|(source, _)| {
+                    Config::resolve_source(root, source).is_ok_and(|source| link::same_ignoring_case(&source, dest))
+                })
+    };
+    let collides = taken(&dest_in_root);
+    if collides {
+        match args.on_collision {
+            OnCollision::Skip => {
+                eprintln!("{:?} already exists in the dotfiles root, skipping.", dest_in_root);
+                return Ok(false);
+            }
+            // a preview looks at every file, so one collision doesn't end it
+            OnCollision::Error if args.preview => {
+                eprintln!("✖ {:?} already exists in the dotfiles root", dest_in_root);
+                return Ok(false);
+            }
+            OnCollision::Error => return Err(Error::Collision(dest_in_root)),
+            OnCollision::Rename => {
+                let is_dir = target.is_dir();
+                dest_in_root = (2..)
+                    .map(|n| dir_in_root.join(numbered_name(name, n, is_dir)))
+                    .find(|dest| !taken(dest))
+                    .expect("some numbered name is free");
+            }
+        }
+    }
+
+    let kind = if args.hardlink {
+        LinkKind::Hardlink
+    } else {
+        cfg.link_kind()
+    };
+    if kind == LinkKind::Hardlink
+        && let Some(reason) = link::hardlink_unsupported(&target, &dest_in_root)
+    {
+        eprintln!("✖ Cannot hard link {:?}: {reason}", target);
+        return Ok(false);
+    }
+
+    if args.preview {
+        if collides {
+            println!(
+                "  {} The name is taken, renamed to {:?}",
+                theme::warning("Warning:"),
+                dest_in_root.file_name().unwrap_or_default()
+            );
+        }
+        println!(
+            "  - Would move {} -> {}",
+            theme::path(format!("{:?}", target.display())),
+            theme::path(format!("{:?}", dest_in_root.display()))
         );
-        return Ok(());
+        println!(
+            "  - Would link {} -> {}",
+            theme::path(format!("{:?}", dest_in_root.display())),
+            theme::path(format!("{:?}", target.display()))
+        );
+        println!("  - Would record {:?} = {:?}", dest_in_root, recorded);
+        return Ok(!collides);
+    }
+
+    // resolved in place, so relative links still point where they should
+    if args.follow_symlinks && target.is_dir() {
+        copy::dereference_tree(&target)?;
     }
 
     // move the original file/dir into the dotfiles root
     println!(
         "  - Moving {} -> {}",
-        format!("{:?}", target.display()).cyan(),
-        format!("{:?}", dest_in_root.display()).cyan()
+        theme::path(format!("{:?}", target.display())),
+        theme::path(format!("{:?}", dest_in_root.display()))
     );
 
-    fs::rename(&target, &dest_in_root)?;
+    mode::create_dir_all(&dir_in_root, cfg.settings.dir_perms)?;
+    copy::move_path(&target, &dest_in_root)?;
+    if let Some(journal) = journal.as_deref_mut() {
+        journal.moved(target.clone(), dest_in_root.clone());
+    }
 
-    cfg.entries.insert(dest_in_root.clone(), target.clone());
+    let mut entry = Entry::new(recorded);
+    if kind != cfg.link_kind() {
+        entry.link = Some(kind);
+    }
+    cfg.entries.insert(expand::escape(&dest_in_root), entry);
 
     let actual_path = &dest_in_root;
     let symlink_target = &target; // `target` is already canonicalized and absolute
@@ -178,251 +931,3066 @@ fn add_one(
     if symlink_target.exists() || fs::symlink_metadata(symlink_target).is_ok() {
         println!(
             "[{}] Symlink target {:?} already exists, skipping.",
-            "Info".yellow(),
+            theme::info("Info"),
             symlink_target
         );
     } else {
-        if let Some(parent) = symlink_target.parent() {
-            fs::create_dir_all(parent)?;
-        }
+        ensure_parent(symlink_target, parents, cfg.settings.dir_perms)?;
 
         println!(
             "  - Linking {} -> {}",
-            format!("{:?}", actual_path.display()).cyan(),
-            format!("{:?}", symlink_target.display()).cyan()
+            theme::path(format!("{:?}", actual_path.display())),
+            theme::path(format!("{:?}", symlink_target.display()))
         );
 
-        std::os::unix::fs::symlink(actual_path, symlink_target)?;
+        kind.create(actual_path, symlink_target)?;
+        if let Some(journal) = journal {
+            journal.linked(symlink_target.clone());
+        }
     }
 
-    fs::write(
-        cfg_path,
-        toml::to_string_pretty(cfg).expect("failed to serialize config"),
-    )
+    Ok(true)
 }
 
-fn resolve_targets(pattern: &str) -> io::Result<Vec<PathBuf>> {
-    Ok(glob(pattern)
-        .expect("Failed to read glob pattern")
-        .filter_map(|i| {
-            i.inspect_err(|e| eprintln!("{} {}", "Glob error:".red(), e.to_string().red()))
-                .ok()
-        })
-        .collect::<Vec<_>>())
+/// `target` the way it goes into the config: relative to `target_base` if
+/// given, where it has to be, and otherwise as `portable_target` writes it.
+fn recorded_target(cfg: &Config, target: &Path, target_base: Option<&Path>) -> Option<PathBuf> {
+    match target_base {
+        Some(base) => match target.strip_prefix(base) {
+            Ok(rel) if !rel.as_os_str().is_empty() => Some(expand::escape(rel)),
+            _ => {
+                eprintln!("✖ {:?} is not inside the target base {:?}", target, base);
+                None
+            }
+        },
+        None => Some(cfg.portable_target(target)),
+    }
 }
 
-fn add(
-    cfg_path: PathBuf,
+/// `add --no-move`: records the symlink `link`, which has to lead into `root`
+/// already, without touching it or its source.
+fn adopt_link(
     cfg: &mut Config,
-    targets: &[String],
-    root: Option<PathBuf>,
-) -> io::Result<()> {
-    let dotlink_root = match root {
-        Some(r) => r,
-        None => cfg.get_root()?,
+    link: &Path,
+    root: &Path,
+    target_base: Option<&Path>,
+    preview: bool,
+) -> Result<bool> {
+    // the link itself is kept, only the directories leading to it are resolved
+    let link = std::path::absolute(link)?.clean();
+    let link = match (link.parent(), link.file_name()) {
+        (Some(dir), Some(name)) => fs::canonicalize(dir)?.join(name),
+        _ => link,
     };
 
-    if !dotlink_root.exists() {
-        eprintln!(
-            "{} Dotfiles root directory `{:?}` does not exist.",
-            "Error:".red(),
-            dotlink_root
-        );
+    if !fs::symlink_metadata(&link).is_ok_and(|m| m.is_symlink()) {
+        eprintln!("✖ {:?} is not a symlink, --no-move only records existing links", link);
+        return Ok(false);
+    }
+
+    let Some(source) = orphans::source_in_root(&link, root) else {
+        eprintln!("✖ {:?} does not lead to a file in the dotfiles root {:?}", link, root);
+        return Ok(false);
+    };
 
-        exit(1);
+    if cfg
+        .entries_under(root)?
+        .any(|entry| cfg.resolve_target(&entry.target).is_ok_and(|target| target == link))
+    {
+        eprintln!("{:?} is already recorded, skipping.", link);
+        return Ok(false);
     }
 
-    for pattern in targets {
-        for path in resolve_targets(pattern)? {
-            println!(
-                "[{}] adding {}",
-                "INFO".yellow(),
-                format!("{:?}", path.display()).bold()
-            );
+    let Some(recorded) = recorded_target(cfg, &link, target_base) else {
+        return Ok(false);
+    };
 
-            add_one(cfg, &cfg_path, path, &dotlink_root)?;
-        }
+    if preview {
+        println!("  - Would record {:?} = {:?}", source, recorded);
+        return Ok(true);
     }
 
-    Ok(())
+    println!(
+        "  - Recording {} -> {}",
+        theme::path(format!("{:?}", link.display())),
+        theme::path(format!("{:?}", source.display()))
+    );
+    Ok(orphans::adopt(cfg, root, source, recorded))
 }
 
-fn unlink(cfg_path: PathBuf, cfg: &mut Config, entries: &[String]) -> io::Result<()> {
-    let mut targets_to_process = HashSet::new();
-    for pattern in entries {
-        for path in resolve_targets(pattern)? {
-            match fs::canonicalize(&path) {
-                Ok(canon_path) => {
-                    targets_to_process.insert(canon_path);
-                }
+/// Resolves every pattern with `resolve_targets`, then leaves out the paths
+/// matched by a pattern starting with `!`, wherever it appears in `patterns`.
+fn resolve_patterns(patterns: &[String]) -> Result<Vec<PathBuf>> {
+    let key = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.clean());
 
-                Err(_) => {
-                    targets_to_process.insert(path.clean());
-                }
-            }
-        }
+    let mut excluded = HashSet::new();
+    for pattern in patterns.iter().filter_map(|p| p.strip_prefix('!')) {
+        excluded.extend(resolve_targets(pattern)?.iter().map(|path| key(path)));
     }
 
-    if targets_to_process.is_empty() {
-        println!("No valid targets found to unlink.");
-        return Ok(());
+    let mut paths = Vec::new();
+    for pattern in patterns.iter().filter(|p| !p.starts_with('!')) {
+        paths.extend(
+            resolve_targets(pattern)?
+                .into_iter()
+                .filter(|path| !excluded.contains(&key(path))),
+        );
     }
 
-    let mut keys_to_remove = Vec::new();
-    let mut changed = false;
+    Ok(paths)
+}
 
-    for (source_path_abs, target_path_unexpanded) in &cfg.entries {
-        let target_path_abs = PathBuf::from(expand_tilde(target_path_unexpanded)).clean();
+fn resolve_targets(pattern: &str) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for pattern in brace::expand(pattern) {
+        let expanded = expand_path(Path::new(&pattern))?;
+        let matches = glob(&expanded.to_string_lossy())
+            .map_err(|source| Error::InvalidPattern { pattern: pattern.clone(), source })?;
+        paths.extend(matches.filter_map(|i| {
+            i.inspect_err(|e| eprintln!("{} {}", theme::error("Glob error:"), theme::error(e)))
+                .ok()
+        }));
+    }
 
-        // Check if either the source (in dotfiles_root) or the target (symlink)
-        // was specified by the user.
-        if targets_to_process.contains(source_path_abs)
-            || targets_to_process.contains(&target_path_abs)
-        {
-            println!(
-                "[{}] Unlinking {}",
-                "INFO".yellow(),
-                format!("{:?}", source_path_abs.file_name().unwrap()).bold()
-            );
+    if paths.is_empty() {
+        eprintln!("{} pattern '{pattern}' matched no files", theme::warning("Warning:"));
+    }
 
-            // remove the symlink.
-            // Use `symlink_metadata` to check the path without following the link
-            if let Ok(metadata) = fs::symlink_metadata(&target_path_abs) {
-                if metadata.file_type().is_symlink() {
-                    println!(
-                        "  - Removing symlink at {}",
-                        format!("{:?}", target_path_abs.display()).cyan()
-                    );
-                    fs::remove_file(&target_path_abs)?;
-                } else {
-                    eprintln!(
-                        "  {} Path at {:?} is not a symlink, but is the target for this entry. Please resolve manually.",
-                        "Warning:".yellow(),
-                        target_path_abs.display()
-                    );
-                }
-            }
+    Ok(paths)
+}
 
-            // move the file/dir from dotfiles_root back to the target location
-            if source_path_abs.exists() {
-                println!(
-                    "  - Moving {} -> {}",
-                    format!("{:?}", source_path_abs.display()).cyan(),
-                    format!("{:?}", target_path_abs.display()).cyan()
-                );
+/// The paths `patterns` resolve to, each once. Overlapping patterns (e.g.
+/// `~/.config/*` and `~/.config/nvim`) can resolve to the same path, which
+/// would be moved on the first pass and then fail confusingly on the second.
+fn unique_paths(patterns: &[String], no_move: bool) -> Result<Vec<PathBuf>> {
+    let mut seen = HashSet::new();
+    let mut paths = Vec::new();
+    for path in resolve_patterns(patterns)? {
+        // links are added as themselves, several of them may lead to the same file
+        let key = if no_move {
+            std::path::absolute(&path)?.clean()
+        } else {
+            fs::canonicalize(&path).unwrap_or_else(|_| path.clean())
+        };
+        if seen.insert(key) {
+            paths.push(path);
+        }
+    }
 
-                // move
-                fs::rename(source_path_abs, &target_path_abs)?;
-            } else {
-                eprintln!(
-                    "  {} Source file {:?} does not exist in dotfiles root. Cannot move it.",
-                    "Warning:".yellow(),
-                    source_path_abs.display()
-                );
+    Ok(paths)
+}
+
+fn add(cfg_path: PathBuf, cfg: &mut Config, args: &AddArgs, parents: bool) -> Result<bool> {
+    let dotlink_root = match args.root.clone() {
+        Some(r) => fs::canonicalize(&r).unwrap_or(r),
+        None => cfg.get_root()?,
+    };
+
+    if !dotlink_root.exists() {
+        return Err(Error::RootNotFound(dotlink_root));
+    }
+
+    // only plain names, so the package directory can't end up outside the root
+    if let Some(prefix) = &args.prefix
+        && !prefix.components().all(|c| matches!(c, Component::Normal(_)))
+    {
+        return Err(Error::InvalidPrefix(prefix.clone()));
+    }
+
+    let target_base = match &args.target_base {
+        Some(base) => Some(fs::canonicalize(expand_path(base)?)?),
+        None => None,
+    };
+    if let Some(base) = &target_base {
+        use_target_base(cfg, base);
+    }
+
+    let paths = unique_paths(&args.targets, args.no_move)?;
+    if paths.is_empty() {
+        eprintln!("{} None of the given patterns matched anything.", theme::error("Error:"));
+        return Ok(false);
+    }
+
+    let paths = if args.interactive {
+        pick_dir_contents(paths)?
+    } else {
+        paths
+    };
+
+    let paths = if args.no_hidden {
+        without_hidden(paths)?
+    } else {
+        paths
+    };
+
+    if args.transaction {
+        return add_transaction(&cfg_path, cfg, paths, &dotlink_root, target_base.as_deref(), args, parents);
+    }
+
+    let (mut added, mut skipped, mut failed) = (0, 0, 0);
+    for path in paths {
+        println!(
+            "[{}] adding {}",
+            theme::info("INFO"),
+            format!("{:?}", path.display()).bold()
+        );
+
+        match add_one(cfg, path.clone(), &dotlink_root, target_base.as_deref(), args, parents, None) {
+            Ok(true) if args.preview => added += 1,
+            Ok(true) => {
+                save_config(cfg, &cfg_path)?;
+                added += 1;
+            }
+            Ok(false) => skipped += 1,
+            Err(e) if args.ignore_conflicts => {
+                eprintln!("  {} Failed to add {:?}: {e}", theme::error("Error:"), path);
+                failed += 1;
             }
+            Err(e) => return Err(e),
+        }
+    }
+
+    if args.preview {
+        println!(
+            "[{}] {added} would be added as they are, {skipped} collide or would be skipped, {failed} failed.",
+            theme::info("INFO")
+        );
+        return Ok(skipped + failed == 0);
+    }
+
+    println!(
+        "[{}] {added} added, {skipped} skipped, {failed} failed.",
+        theme::info("INFO")
+    );
+
+    Ok(failed == 0)
+}
+
+/// `add --transaction`: adds every path in `paths`, saving the config once at
+/// the end. If one of them is skipped or fails, the files added before it are
+/// moved back, their links removed, and the config is left as it was.
+fn add_transaction(
+    cfg_path: &Path,
+    cfg: &mut Config,
+    paths: Vec<PathBuf>,
+    root: &Path,
+    target_base: Option<&Path>,
+    args: &AddArgs,
+    parents: bool,
+) -> Result<bool> {
+    let mut journal = Journal::default();
+    let total = paths.len();
+    for path in paths {
+        println!(
+            "[{}] adding {}",
+            theme::info("INFO"),
+            format!("{:?}", path.display()).bold()
+        );
 
-            // mark this entry's key for removal from the config.
-            keys_to_remove.push(source_path_abs.clone());
-            changed = true;
+        let result = add_one(cfg, path.clone(), root, target_base, args, parents, Some(&mut journal));
+        if let Ok(true) = result {
+            continue;
+        }
+
+        println!(
+            "[{}] {:?} was not added, rolling back the batch...",
+            theme::info("INFO"),
+            path
+        );
+        if journal.roll_back() {
+            println!("[{}] Nothing was added.", theme::info("INFO"));
+        } else {
+            eprintln!(
+                "{}",
+                theme::error("✖ Some changes could not be undone, the files above need to be put back by hand.")
+            );
         }
+        // the error of the file that failed, if any, is reported last
+        return result;
     }
 
-    // update the config if changes were made
-    if changed {
-        println!("[{}] Updating config file...", "INFO".yellow());
-        for key in keys_to_remove {
-            cfg.entries.remove(&key);
+    save_config(cfg, cfg_path)?;
+    println!("[{}] {total} added.", theme::info("INFO"));
+    Ok(true)
+}
+
+/// `name` with `-<n>` added, before the extension for files (`init.lua` becomes
+/// `init-2.lua`) and at the end for directories and names without one (`.bashrc-2`).
+fn numbered_name(name: &OsStr, n: u32, is_dir: bool) -> PathBuf {
+    let path = Path::new(name);
+    match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(ext)) if !is_dir => {
+            let mut numbered = stem.to_os_string();
+            numbered.push(format!("-{n}."));
+            numbered.push(ext);
+            numbered.into()
         }
+        _ => {
+            let mut numbered = name.to_os_string();
+            numbered.push(format!("-{n}"));
+            numbered.into()
+        }
+    }
+}
 
-        fs::write(
-            &cfg_path,
-            toml::to_string_pretty(cfg).expect("Failed to serialize config"),
-        )?;
-        println!("✅ Unlink operation complete.");
-    } else {
-        println!("No matching entries found in config for the given paths.");
+/// A free path next to `path` to move it aside to: `<name>.bak`, or
+/// `<name>.bak-2` and so on when that is taken.
+fn backup_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or(path.as_os_str());
+    let with_suffix = |suffix: String| {
+        let mut backup = name.to_os_string();
+        backup.push(suffix);
+        path.with_file_name(backup)
+    };
+
+    std::iter::once(with_suffix(".bak".to_string()))
+        .chain((2..).map(|n| with_suffix(format!(".bak-{n}"))))
+        .find(|backup| fs::symlink_metadata(backup).is_err())
+        .expect("some numbered name is free")
+}
+
+/// Replaces directories with hidden files or directories somewhere below them
+/// by their visible children, recursively, so the hidden ones stay where they
+/// are. The given paths are kept even if they are hidden themselves.
+fn without_hidden(paths: Vec<PathBuf>) -> io::Result<Vec<PathBuf>> {
+    let mut kept = Vec::new();
+    for path in paths {
+        split_hidden(path, &mut kept)?;
+    }
+    Ok(kept)
+}
+
+fn split_hidden(path: PathBuf, kept: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !has_hidden(&path)? {
+        kept.push(path);
+        return Ok(());
+    }
+
+    let mut children = fs::read_dir(&path)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    children.sort();
+
+    for child in children {
+        if is_hidden(&child) {
+            println!("  - Skipping hidden {}", theme::path(format!("{:?}", child.display())));
+        } else {
+            split_hidden(child, kept)?;
+        }
     }
 
     Ok(())
 }
 
-fn fix(cfg: &Config) -> io::Result<()> {
-    println!("[{}] Checking and fixing links...", "INFO".yellow());
-    let mut all_ok = true;
+fn has_hidden(path: &Path) -> io::Result<bool> {
+    if !fs::symlink_metadata(path)?.is_dir() {
+        return Ok(false);
+    }
 
-    for (name, source, target) in cfg.entries()? {
-        let target_path = PathBuf::from(expand_tilde(&target));
-        let name_os_str = name.file_name().unwrap_or(name.as_os_str());
+    for child in fs::read_dir(path)? {
+        let child = child?.path();
+        if is_hidden(&child) || has_hidden(&child)? {
+            return Ok(true);
+        }
+    }
 
-        if !source.exists() {
-            eprintln!("✖ Source missing for {:?}: {:?}", name_os_str, source);
-            all_ok = false;
+    Ok(false)
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name.as_bytes().starts_with(b"."))
+}
+
+/// Replaces each directory in `paths` with the entries of it the user picks.
+fn pick_dir_contents(paths: Vec<PathBuf>) -> io::Result<Vec<PathBuf>> {
+    let mut picked = Vec::new();
+
+    for path in paths {
+        if !path.is_dir() {
+            picked.push(path);
             continue;
         }
 
-        match fs::symlink_metadata(&target_path) {
-            Ok(metadata) => {
-                // Target path exists.
-                if metadata.file_type().is_symlink() {
-                    // It's a symlink, check if it points to the correct source.
-                    let actual_link_target = fs::read_link(&target_path)?;
-                    if actual_link_target != source {
-                        eprintln!(
-                            "⚠ Symlink mismatch for {:?}: {:?} points to {:?}, expected {:?}",
-                            name_os_str, target, actual_link_target, source
-                        );
-                        all_ok = false;
-                    } else {
-                        println!(
-                            "{}",
-                            format!("󰄬 {:?} -> {:?} [ok]", name_os_str, target.display())
-                                .white()
-                                .bold()
-                        );
+        if !prompt::is_interactive() {
+            eprintln!(
+                "{} stdin is not a terminal, adding {:?} as a whole.",
+                theme::warning("Warning:"),
+                path
+            );
+            picked.push(path);
+            continue;
+        }
+
+        let mut children = fs::read_dir(&path)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        children.sort();
+
+        if children.is_empty() {
+            println!("[{}] {:?} is empty, skipping.", theme::info("INFO"), path);
+            continue;
+        }
+
+        println!("[{}] Contents of {:?}:", theme::info("INFO"), path);
+        let names = children
+            .iter()
+            .map(|c| c.file_name().unwrap_or_default().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+
+        for i in prompt::select(&names)? {
+            picked.push(children[i].clone());
+        }
+    }
+
+    Ok(picked)
+}
+
+/// Reads the paths piped into `unlink --stdin`, NUL-separated if the input
+/// contains a NUL byte (`find -print0`), newline-separated otherwise.
+fn read_stdin_paths() -> io::Result<Vec<PathBuf>> {
+    let mut input = Vec::new();
+    io::stdin().read_to_end(&mut input)?;
+
+    let separator = if input.contains(&0) { b'\0' } else { b'\n' };
+    Ok(input
+        .split(|&b| b == separator)
+        .filter(|path| !path.is_empty())
+        .map(|path| PathBuf::from(OsStr::from_bytes(path)))
+        .collect())
+}
+
+/// Unlinks the entries matching the `entries` patterns or one of the literal `paths`.
+fn unlink(
+    cfg_path: PathBuf,
+    cfg: &mut Config,
+    args: &UnlinkArgs,
+    paths: &[PathBuf],
+    parents: bool,
+) -> Result<bool> {
+    let root = cfg.get_root()?;
+
+    // an alias names its entry, even if there is a file of that name as well
+    let same = |alias: &str, arg: &str| {
+        if args.ignore_case {
+            alias.to_lowercase() == arg.to_lowercase()
+        } else {
+            alias == arg
+        }
+    };
+    let mut aliases = HashSet::new();
+    let mut patterns = Vec::new();
+    for arg in &args.entries {
+        let matching: Vec<_> = cfg
+            .all_entries()
+            .filter_map(|(_, entry)| entry.alias.as_deref())
+            .filter(|alias| same(alias, arg))
+            .collect();
+        if matching.is_empty() {
+            patterns.push(arg.clone());
+        }
+        aliases.extend(matching);
+    }
+
+    // an entry whose source cannot be resolved is reported as skipped below
+    let mut targets_to_process: HashSet<_> = cfg
+        .sources_by_alias(&aliases)
+        .iter()
+        .filter_map(|source| Config::resolve_source(&root, source).ok())
+        .collect();
+
+    let (resolved, caseless) = if args.ignore_case {
+        (Vec::new(), Some(caseless::Patterns::new(&patterns)?))
+    } else {
+        (resolve_patterns(&patterns)?, None)
+    };
+    for path in resolved.iter().chain(paths) {
+        match fs::canonicalize(path) {
+            Ok(canon_path) => {
+                targets_to_process.insert(canon_path);
+            }
+
+            Err(_) => {
+                targets_to_process.insert(path.clean());
+            }
+        }
+    }
+
+    if targets_to_process.is_empty() && (caseless.is_none() || patterns.is_empty()) {
+        println!("No valid targets found to unlink.");
+        return Ok(true);
+    }
+
+    // the user's paths, or with --ignore-case the paths the patterns match
+    let wanted = |path: &Path| {
+        targets_to_process.contains(path) || caseless.as_ref().is_some_and(|patterns| patterns.matches(path))
+    };
+
+    let mut keys_to_remove = Vec::new();
+    let mut changed = false;
+    let mut privileged = Batch::default();
+    let mut failures = Failures::new(args.keep_going);
+    // directories inside these may be removed once empty, the boundaries themselves never
+    let boundaries = if args.delete_empty_dirs {
+        vec![expand_path(Path::new("~"))?.clean(), cfg.target_base()?]
+    } else {
+        Vec::new()
+    };
+
+    for (key, entry) in cfg.own_entries() {
+        let resolved = Config::resolve_source(&root, key).and_then(|source| {
+            let targets = entry
+                .targets
+                .iter()
+                .map(|target| cfg.resolve_target(target))
+                .collect::<io::Result<Vec<_>>>()?;
+            Ok((source, targets))
+        });
+        let (source_path_abs, target_paths) = match resolved {
+            Ok(paths) => paths,
+            Err(e) => {
+                eprintln!("  {} Skipping {:?}: {e}", theme::warning("Warning:"), key);
+                continue;
+            }
+        };
+
+        // Check if either the source (in dotfiles_root) or one of the targets (symlinks)
+        // was specified by the user.
+        if wanted(&source_path_abs) || target_paths.iter().any(|target| wanted(target)) {
+            println!(
+                "[{}] Unlinking {}",
+                theme::info("INFO"),
+                format!("{:?}", source_path_abs.file_name().unwrap()).bold()
+            );
+
+            let dir_perms = cfg.settings.dir_perms;
+            match unlink_entry(entry, &source_path_abs, &target_paths, &mut privileged, parents, dir_perms) {
+                Ok(()) => {
+                    // links of privileged entries are only removed when the batch runs
+                    if !entry.privileged {
+                        for target in &target_paths {
+                            delete_empty_dirs(target, &boundaries);
+                        }
                     }
-                } else {
-                    // it's a file or directory, not a symlink. This is a conflict
-                    eprintln!("✖ Conflict: {:?} exists and is not a symlink.", target);
-                    all_ok = false;
+
+                    // mark this entry's key for removal from the config.
+                    keys_to_remove.push(key.clone());
+                    changed = true;
                 }
+                result => failures.record(key, result)?,
             }
-            Err(e) if e.kind() == io::ErrorKind::NotFound => {
-                // target path does not exist. This is where we "fix" it
-                println!(
-                    "{}",
-                    format!(
-                        "󰜺 Missing link for {:?}: {:?} -> {:?}. Creating...",
-                        name_os_str,
-                        source.file_name().unwrap(),
-                        target.display()
-                    )
-                    .blue()
-                );
+        }
+    }
 
-                // ensure parent directory exists before creating symlink
-                if let Some(parent) = target_path.parent() {
-                    fs::create_dir_all(parent)?;
-                }
+    privileged.run()?;
 
-                // create the symlink
-                std::os::unix::fs::symlink(&source, &target_path)?;
-                println!(
-                    "  {}",
-                    format!("Successfully created link for {:?}", name_os_str).green()
-                );
+    // entries from included files cannot be removed from here
+    for (key, (file, entry)) in &cfg.included {
+        let target_matches = entry
+            .targets
+            .iter()
+            .any(|target| cfg.resolve_target(target).is_ok_and(|target| wanted(&target)));
+
+        let source_matches = Config::resolve_source(&root, key).is_ok_and(|source| wanted(&source));
+
+        if source_matches || target_matches {
+            eprintln!(
+                "  {} {:?} is defined in included config {:?} and cannot be unlinked from here.",
+                theme::warning("Warning:"),
+                key,
+                file
+            );
+        }
+    }
+
+    // update the config if changes were made
+    if changed {
+        println!("[{}] Updating config file...", theme::info("INFO"));
+        for key in keys_to_remove {
+            cfg.entries_containing(&key).remove(&key);
+        }
+
+        save_config(cfg, &cfg_path)?;
+        println!("✅ Unlink operation complete.");
+    } else if failures.is_empty() {
+        println!("No matching entries found in config for the given paths.");
+    }
+
+    failures.report();
+    Ok(failures.is_empty())
+}
+
+/// Removes the directories leading to `target` that are empty now, from its
+/// parent upwards, as long as they are inside one of `boundaries`. Failing to
+/// remove one is only warned about.
+fn delete_empty_dirs(target: &Path, boundaries: &[PathBuf]) {
+    for dir in target.ancestors().skip(1) {
+        if !boundaries.iter().any(|boundary| dir.starts_with(boundary) && dir != boundary) {
+            break;
+        }
+        if !fs::read_dir(dir).is_ok_and(|mut children| children.next().is_none()) {
+            break;
+        }
+
+        println!("  - Removing empty directory {}", theme::path(format!("{:?}", dir.display())));
+        if let Err(e) = fs::remove_dir(dir) {
+            eprintln!("  {} Cannot remove {:?}: {e}", theme::warning("Warning:"), dir);
+            break;
+        }
+    }
+}
+
+/// Removes the links of one entry and moves its `source` back to the first of its `targets`.
+fn unlink_entry(
+    entry: &Entry,
+    source: &Path,
+    targets: &[PathBuf],
+    privileged: &mut Batch,
+    parents: bool,
+    dir_perms: Option<Mode>,
+) -> Result<()> {
+    let folded = entry.dir_mode == Some(DirMode::Fold) && fs::symlink_metadata(source).is_ok_and(|m| m.is_dir());
+    if !folded {
+        return unlink_paths(entry, source, targets, privileged, parents, dir_perms);
+    }
+
+    // the target directories are real ones, so every file goes back on its own
+    for leaf in link::leaves(source)? {
+        let targets: Vec<_> = targets.iter().map(|target| target.join(&leaf)).collect();
+        unlink_paths(entry, &source.join(&leaf), &targets, privileged, parents, dir_perms)?;
+    }
+
+    // what is left of the source are its directories, deepest first
+    let mut dirs = vec![source.to_path_buf()];
+    let mut i = 0;
+    while i < dirs.len() {
+        for child in fs::read_dir(&dirs[i])? {
+            let child = child?;
+            if child.file_type()?.is_dir() {
+                dirs.push(child.path());
             }
-            Err(e) => {
-                eprintln!("✖ Error checking path {:?}: {}", target_path, e);
-                all_ok = false;
+        }
+        i += 1;
+    }
+    for dir in dirs.into_iter().rev() {
+        if entry.privileged {
+            privileged.push(Op::RemoveDir(dir));
+        } else {
+            fs::remove_dir(&dir)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes the links at `targets` and moves `source` back to the first of them.
+fn unlink_paths(
+    entry: &Entry,
+    source: &Path,
+    targets: &[PathBuf],
+    privileged: &mut Batch,
+    parents: bool,
+    dir_perms: Option<Mode>,
+) -> Result<()> {
+    // remove the symlinks.
+    // Use `symlink_metadata` to check the paths without following the links
+    for target in targets {
+        let Ok(metadata) = fs::symlink_metadata(target) else {
+            continue;
+        };
+
+        // a hard link or an alias has to go too, moving the source onto it would be a no-op or fail
+        let kind = if metadata.file_type().is_symlink() {
+            Some(LinkKind::Symlink)
+        } else if link::same_file(source, target) {
+            Some(LinkKind::Hardlink)
+        } else if alias::SUPPORTED
+            && alias::original(target).is_ok_and(|original| original.is_some_and(|o| alias::leads_to(&o, source)))
+        {
+            Some(LinkKind::Alias)
+        } else {
+            None
+        };
+
+        if let Some(kind) = kind {
+            println!(
+                "  - Removing {} at {}",
+                kind.name(),
+                theme::path(format!("{:?}", target.display()))
+            );
+
+            if entry.privileged {
+                privileged.push(Op::Remove(target.clone()));
+            } else {
+                fs::remove_file(target)?;
             }
+        } else {
+            eprintln!(
+                "  {} Path at {:?} is not a symlink, but is the target for this entry. Please resolve manually.",
+                theme::warning("Warning:"),
+                target.display()
+            );
         }
     }
 
-    if all_ok {
-        println!("\n✅ All links are correct.");
+    // move the file/dir from dotfiles_root back to the (first) target location
+    let target = &targets[0];
+    if source.exists() {
+        println!(
+            "  - Moving {} -> {}",
+            theme::path(format!("{:?}", source.display())),
+            theme::path(format!("{:?}", target.display()))
+        );
+
+        // the target's parent may have been removed since the link was made
+        if entry.privileged {
+            match target.parent() {
+                Some(parent) if parents => privileged.push(Op::CreateDir(parent.to_path_buf())),
+                _ => ensure_parent(target, false, None)?,
+            }
+            privileged.push(Op::Move {
+                from: source.to_path_buf(),
+                to: target.clone(),
+            });
+        } else {
+            ensure_parent(target, parents, dir_perms)?;
+            copy::move_path(source, target)?;
+        }
     } else {
-        println!("\n❌ Some issues were found.");
+        eprintln!(
+            "  {} Source file {:?} does not exist in dotfiles root. Cannot move it.",
+            theme::warning("Warning:"),
+            source.display()
+        );
     }
 
     Ok(())
 }
+
+fn fix(cfg: &Config, args: &FixArgs, parents: bool) -> Result<bool> {
+    if args.confirm_each && !prompt::is_interactive() {
+        eprintln!("stdin is not a terminal, run without --confirm-each to make every change.");
+        return Ok(false);
+    }
+
+    if args.dry_run {
+        println!("[{}] Dry run, nothing will be changed.", theme::info("INFO"));
+    }
+
+    println!("[{}] Checking and fixing links...", theme::info("INFO"));
+    let root = cfg.get_root()?;
+    let mut failures = Failures::new(args.keep_going);
+
+    let targets: HashSet<PathBuf> = cfg
+        .entries_under(&root)?
+        .filter(|entry| entry.enabled)
+        .filter_map(|entry| cfg.resolve_target(&entry.target).ok())
+        .collect();
+
+    let changed = args
+        .since_commit
+        .as_deref()
+        .and_then(|rev| changed::since_commit(&root, rev));
+
+    // a target that cannot be resolved can't be placed either, so it is left out too
+    let only_dirs = only_dirs(&args.only_dir)?;
+    let in_scope = |entry: &ResolvedEntry| {
+        only_dirs.is_empty() || cfg.resolve_target(&entry.target).is_ok_and(|target| is_under(&target, &only_dirs))
+    };
+
+    let mut run = FixRun {
+        cfg,
+        args,
+        parents,
+        retry: Retry::new(cfg.settings.retries, cfg.settings.retry_delay_ms),
+        nested: nested_targets(&targets).into_iter().collect(),
+        root,
+        privileged: Batch::default(),
+        post_privileged: Vec::new(),
+        made: Vec::new(),
+        confirm_each: args.confirm_each.then(ConfirmEach::default),
+        all_ok: true,
+        unchanged: 0,
+        changed,
+        healthy: 0,
+        disabled: 0,
+    };
+
+    if args.dry_run
+        && let Some(reason) = preflight::root(&run.root)
+    {
+        eprintln!("{}", theme::error(format!("✖ Would fail: {reason}")));
+        run.all_ok = false;
+    }
+
+    let mut total = 0;
+    let mut out_of_scope = 0;
+    for entry in cfg.entries_under(&run.root)? {
+        if !in_scope(&entry) {
+            out_of_scope += 1;
+            continue;
+        }
+        total += 1;
+        let result = run.entry(&entry);
+        failures.record(&entry.name, result)?;
+    }
+
+    std::mem::take(&mut run.privileged).run()?;
+    for hook in std::mem::take(&mut run.post_privileged) {
+        run.run_hook(hook);
+    }
+
+    if args.verify {
+        run.verify();
+    }
+
+    // links share the permissions of what they point to, so the sources are changed
+    let mut chowns = Batch::default();
+    for entry in cfg.entries_under(&run.root)?.filter(|entry| in_scope(entry)) {
+        if !entry.enabled || fs::symlink_metadata(&entry.source).is_err() {
+            continue;
+        }
+
+        // before the mode, which `chown` may clear setuid and setgid bits of
+        if entry.owner.is_some() || entry.group.is_some() {
+            let batch = entry.privileged.then_some(&mut chowns);
+            let result = owner::resolve(entry.owner.as_deref(), entry.group.as_deref())
+                .and_then(|(uid, gid)| owner::enforce(&entry.source, uid, gid, args.dry_run, batch));
+            failures.record(&entry.name, result.map_err(Error::from))?;
+        }
+
+        if entry.mode.is_some() || args.chmod_files.is_some() || args.chmod_dirs.is_some() {
+            let result = mode::normalize(
+                &entry.source,
+                entry.mode,
+                args.chmod_files,
+                args.chmod_dirs,
+                args.dry_run,
+            );
+            failures.record(&entry.name, result.map_err(Error::from))?;
+        }
+    }
+    chowns.run()?;
+
+    if run.unchanged > 0 {
+        println!("\n[{}] Skipped {} unchanged entries.", theme::info("INFO"), run.unchanged);
+    }
+
+    if out_of_scope > 0 {
+        println!("\n[{}] Left out {} entries outside --only-dir.", theme::info("INFO"), out_of_scope);
+    }
+
+    if args.quiet {
+        println!(
+            "\n[{}] {} entries already linked, {} disabled.",
+            theme::info("INFO"),
+            run.healthy,
+            run.disabled
+        );
+    }
+
+    if let Some(path) = &args.report_file {
+        let mut entries = Vec::new();
+        let summary = status::inspect(cfg, |entry| entries.push(entry.clone()))?;
+        Report {
+            entries: &entries,
+            summary: &summary,
+        }
+        .write(path)?;
+    }
+
+    failures.report();
+    let mut all_ok = run.all_ok && failures.is_empty();
+
+    // even links that were just made mean the machine was not in the state the config describes
+    let deviations = total - run.disabled - run.unchanged - run.healthy;
+    if args.strict && deviations > 0 {
+        eprintln!(
+            "{}",
+            theme::error(format!(
+                "✖ --strict: {deviations} of {} entries were not linked correctly before this run.",
+                total - run.disabled - run.unchanged
+            ))
+        );
+        all_ok = false;
+    }
+
+    if all_ok {
+        println!("\n✅ All links are correct.");
+    } else {
+        println!("\n❌ Some issues were found.");
+    }
+
+    Ok(all_ok)
+}
+
+/// The directories of `fix --only-dir`, absolute and cleaned, each followed by
+/// its resolved form if a symlink leads there, so targets match either.
+fn only_dirs(dirs: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    let mut resolved = Vec::new();
+    for dir in dirs {
+        let dir = std::path::absolute(expand_path(dir)?)?.clean();
+        let real = fs::canonicalize(&dir).ok().filter(|real| *real != dir);
+        resolved.push(dir);
+        resolved.extend(real);
+    }
+    Ok(resolved)
+}
+
+/// Whether `target` lies below one of `dirs`, as written or with the
+/// directories leading to it resolved. The link itself is not followed.
+fn is_under(target: &Path, dirs: &[PathBuf]) -> bool {
+    let below = |path: &Path| dirs.iter().any(|dir| path.starts_with(dir) && path != dir);
+    let on_disk = target
+        .parent()
+        .and_then(|parent| fs::canonicalize(parent).ok())
+        .map(|parent| parent.join(target.file_name().unwrap_or_default()));
+
+    below(target) || on_disk.is_some_and(|target| below(&target))
+}
+
+/// What a `fix` run shares between entries.
+struct FixRun<'a> {
+    cfg: &'a Config,
+    args: &'a FixArgs,
+    parents: bool,
+    root: PathBuf,
+    retry: Retry,
+    /// Targets inside the target of another entry, with that target
+    nested: HashMap<PathBuf, PathBuf>,
+    privileged: Batch,
+    /// `post_link` commands of privileged entries, run once the batch is done
+    post_privileged: Vec<Hook>,
+    /// `(name, source, target, kind)` of every link made, for `--verify`
+    made: Vec<(PathBuf, PathBuf, PathBuf, LinkKind)>,
+    confirm_each: Option<ConfirmEach>,
+    all_ok: bool,
+    unchanged: usize,
+    /// Files changed since `--since-commit`, if git could tell
+    changed: Option<Vec<PathBuf>>,
+    healthy: usize,
+    disabled: usize,
+}
+
+impl FixRun<'_> {
+    /// Checks the link of one entry and creates or repairs it.
+    fn entry(&mut self, entry: &ResolvedEntry) -> Result<()> {
+        let ResolvedEntry {
+            name,
+            source,
+            target,
+            ..
+        } = entry;
+
+        let name_os_str = name.file_name().unwrap_or(name.as_os_str());
+        if self.args.explain {
+            println!(
+                "  {} {:?} {}",
+                theme::info("Explain:"),
+                name_os_str,
+                entry.origin.explain(entry.enabled)
+            );
+        }
+
+        if !entry.enabled {
+            self.disabled += 1;
+            if !self.args.quiet {
+                println!("- {:?} -> {:?} [disabled]", name_os_str, target.display());
+            }
+            return Ok(());
+        }
+
+        let mut target_path = match self.cfg.resolve_target(target) {
+            Ok(path) => path,
+            Err(e) => {
+                eprintln!("✖ Cannot resolve target for {:?}: {e}", name_os_str);
+                self.all_ok = false;
+                return Ok(());
+            }
+        };
+
+        if let Some(outer) = self.nested.get(&target_path) {
+            eprintln!(
+                "✖ Refusing to link {:?}: target {:?} is inside the target {:?} of another entry",
+                name_os_str, target_path, outer
+            );
+            self.all_ok = false;
+            return Ok(());
+        }
+
+        if entry.folded
+            && let Some(ancestor) = linked_into_root(&target_path, &self.root)
+        {
+            eprintln!(
+                "✖ Refusing to link {:?}: {:?} is a symlink into the dotfiles root, but the entry is folded; \
+                 remove it so the directory can be created",
+                name_os_str, ancestor
+            );
+            self.all_ok = false;
+            return Ok(());
+        }
+
+        if overlaps_root(&target_path, &self.root) {
+            eprintln!(
+                "✖ Refusing to link {:?}: target {:?} overlaps the dotfiles root {:?}",
+                name_os_str, target_path, self.root
+            );
+            self.all_ok = false;
+            return Ok(());
+        }
+
+        if let Some(ancestor) = symlinked_ancestor(&target_path) {
+            let resolved = match fs::canonicalize(&ancestor) {
+                Ok(dir) => dir.join(target_path.strip_prefix(&ancestor).unwrap_or(&target_path)),
+                Err(e) => {
+                    self.unreadable(name_os_str, &ancestor, e);
+                    return Ok(());
+                }
+            };
+
+            match self.args.dereference_target {
+                DereferenceTarget::Warn => eprintln!(
+                    "  {} {:?} is a symlink, the link for {:?} ends up at {:?}",
+                    theme::warning("Warning:"),
+                    ancestor,
+                    name_os_str,
+                    resolved
+                ),
+                DereferenceTarget::Resolve => {
+                    println!(
+                        "[{}] {:?} is a symlink, linking {:?} at {:?}",
+                        theme::info("INFO"),
+                        ancestor,
+                        name_os_str,
+                        resolved
+                    );
+                    target_path = resolved;
+                }
+                DereferenceTarget::Refuse => {
+                    eprintln!(
+                        "✖ Refusing to link {:?}: {:?} is a symlink (the link would end up at {:?})",
+                        name_os_str, ancestor, resolved
+                    );
+                    self.all_ok = false;
+                    return Ok(());
+                }
+            }
+        }
+
+        if !source.exists() {
+            eprintln!("✖ Source missing for {:?}: {:?}", name_os_str, source);
+            self.all_ok = false;
+            return Ok(());
+        }
+
+        let resolved_source;
+        let source = match self.args.source_symlinks {
+            SourceSymlinks::Resolve if fs::symlink_metadata(source).is_ok_and(|m| m.is_symlink()) => {
+                resolved_source = match fs::canonicalize(source) {
+                    Ok(resolved) => resolved,
+                    Err(e) => {
+                        self.unreadable(name_os_str, source, e);
+                        return Ok(());
+                    }
+                };
+                &resolved_source
+            }
+            _ => source,
+        };
+
+        // a directory source counts as changed when anything inside it did
+        if let Some(changed) = &self.changed
+            && !changed.iter().any(|path| path.starts_with(&entry.source))
+        {
+            self.unchanged += 1;
+            return Ok(());
+        }
+
+        if let Some(since) = self.args.since {
+            match fs::metadata(source).and_then(|m| m.modified()) {
+                Ok(modified) if modified < since => {
+                    self.unchanged += 1;
+                    return Ok(());
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    self.unreadable(name_os_str, source, e);
+                    return Ok(());
+                }
+            }
+        }
+
+        if entry.link == LinkKind::Hardlink
+            && let Some(reason) = link::hardlink_unsupported(source, &target_path)
+        {
+            eprintln!("✖ Cannot hard link {:?}: {reason}", name_os_str);
+            self.all_ok = false;
+            return Ok(());
+        }
+
+        match self.retry.run(&target_path, |p| fs::symlink_metadata(p)) {
+            Ok(metadata) => {
+                // Target path exists.
+                if metadata.file_type().is_symlink() && entry.link != LinkKind::Symlink {
+                    eprintln!(
+                        "✖ Conflict: {:?} is a symlink, expected a {} to {:?}",
+                        target,
+                        entry.link.name(),
+                        source
+                    );
+                    self.resolve_conflict(entry, source, target_path)?;
+                } else if metadata.file_type().is_symlink() {
+                    // It's a symlink, check if it points to the correct source.
+                    let actual_link_target = match self.retry.run(&target_path, |p| fs::read_link(p)) {
+                        Ok(link) => link,
+                        Err(e) => {
+                            self.unreadable(name_os_str, &target_path, e);
+                            return Ok(());
+                        }
+                    };
+                    let broken = actual_link_target != *source
+                        && fs::metadata(&target_path).is_err_and(|e| e.kind() == io::ErrorKind::NotFound);
+
+                    if actual_link_target != *source
+                        && (self.args.repoint || (self.args.clobber_broken && broken))
+                    {
+                        // only the symlink itself is replaced, whatever it points to is left alone
+                        println!(
+                            "{}",
+                            theme::action(format!(
+                                "󰑓 {} {:?}: {:?} -> {:?} (was {:?})",
+                                if broken { "Replacing broken link" } else { "Repointing" },
+                                name_os_str,
+                                target.display(),
+                                source,
+                                actual_link_target
+                            ))
+                        );
+
+                        if self.args.dry_run {
+                            if let Some(reason) =
+                                preflight::link(&target_path, self.parents, entry.privileged)
+                            {
+                                eprintln!("  {}", theme::error(format!("✖ Would fail: {reason}")));
+                                self.all_ok = false;
+                            }
+                            return Ok(());
+                        }
+
+                        if let Some(confirm_each) = &mut self.confirm_each
+                            && !confirm_each.ask("  Replace it?")?
+                        {
+                            println!("  Skipped.");
+                            self.all_ok = false;
+                            return Ok(());
+                        }
+
+                        if let Some(hook) = self.hook("pre_link", &entry.pre_link, entry, source, &target_path)
+                            && !self.run_hook(hook)
+                        {
+                            return Ok(());
+                        }
+                        let post_link = self.hook("post_link", &entry.post_link, entry, source, &target_path);
+
+                        if entry.privileged {
+                            self.made(entry, source, &target_path);
+                            self.privileged.push(Op::Remove(target_path.clone()));
+                            self.privileged.push(Op::link(entry.link, source.clone(), target_path));
+                            self.post_privileged.extend(post_link);
+                        } else {
+                            let replaced = fs::remove_file(&target_path)
+                                .and_then(|()| entry.link.create(source, &target_path));
+                            if let Err(e) = replaced {
+                                self.cannot_link(name_os_str, e);
+                                return Ok(());
+                            }
+                            self.made(entry, source, &target_path);
+                            println!(
+                                "  {}",
+                                theme::ok(format!(
+                                    "Successfully repointed link for {:?}",
+                                    name_os_str
+                                ))
+                            );
+                            if let Some(hook) = post_link {
+                                self.run_hook(hook);
+                            }
+                        }
+                    } else if actual_link_target != *source {
+                        eprintln!(
+                            "⚠ Symlink mismatch for {:?}: {:?} points to {:?}, expected {:?}",
+                            name_os_str, target, actual_link_target, source
+                        );
+                        if self.args.verbose {
+                            explain_mismatch(&target_path, &actual_link_target, source);
+                        }
+                        self.all_ok = false;
+                    } else {
+                        self.healthy += 1;
+                        if !self.args.quiet {
+                            println!(
+                                "{}",
+                                format!("󰄬 {:?} -> {:?} [ok]", name_os_str, target.display()).bold()
+                            );
+                        }
+                    }
+                } else if entry.link == LinkKind::Hardlink && link::same_file(source, &target_path) {
+                    // same inode, so it's a hard link to the source
+                    self.healthy += 1;
+                    if !self.args.quiet {
+                        println!(
+                            "{}",
+                            format!("󰄬 {:?} -> {:?} [ok]", name_os_str, target.display()).bold()
+                        );
+                    }
+                } else if entry.link == LinkKind::Hardlink {
+                    eprintln!(
+                        "✖ Conflict: {:?} exists and is not a hard link to {:?}.",
+                        target, source
+                    );
+                    self.resolve_conflict(entry, source, target_path)?;
+                } else if entry.link == LinkKind::Alias {
+                    match alias::original(&target_path) {
+                        Ok(Some(original)) if alias::leads_to(&original, source) => {
+                            self.healthy += 1;
+                            if !self.args.quiet {
+                                println!(
+                                    "{}",
+                                    format!("󰄬 {:?} -> {:?} [ok]", name_os_str, target.display()).bold()
+                                );
+                            }
+                        }
+                        // only symlinks are repointed, an alias is left for the user to replace
+                        Ok(Some(original)) => {
+                            eprintln!(
+                                "⚠ Alias mismatch for {:?}: {:?} refers to {:?}, expected {:?}",
+                                name_os_str, target, original, source
+                            );
+                            self.all_ok = false;
+                        }
+                        Ok(None) => {
+                            eprintln!(
+                                "✖ Conflict: {:?} exists and is not a Finder alias to {:?}.",
+                                target, source
+                            );
+                            self.resolve_conflict(entry, source, target_path)?;
+                        }
+                        Err(e) => self.unreadable(name_os_str, &target_path, e),
+                    }
+                } else {
+                    // it's a file or directory, not a symlink. This is a conflict
+                    eprintln!("✖ Conflict: {:?} exists and is not a symlink.", target);
+                    self.resolve_conflict(entry, source, target_path)?;
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                // target path does not exist. This is where we "fix" it
+                println!(
+                    "{}",
+                    theme::action(format!(
+                        "󰜺 Missing link for {:?}: {:?} -> {:?}. Creating...",
+                        name_os_str,
+                        source.file_name().unwrap(),
+                        target.display()
+                    ))
+                );
+
+                if self.args.dry_run {
+                    if let Some(reason) = preflight::link(&target_path, self.parents, entry.privileged) {
+                        eprintln!("  {}", theme::error(format!("✖ Would fail: {reason}")));
+                        self.all_ok = false;
+                    }
+                    return Ok(());
+                }
+
+                let question = match target_path.parent() {
+                    Some(parent) if self.parents && !parent.is_dir() => {
+                        format!("  Create it and its parent directory {parent:?}?")
+                    }
+                    _ => "  Create it?".to_string(),
+                };
+                if let Some(confirm_each) = &mut self.confirm_each
+                    && !confirm_each.ask(&question)?
+                {
+                    println!("  Skipped.");
+                    self.all_ok = false;
+                    return Ok(());
+                }
+
+                if let Some(hook) = self.hook("pre_link", &entry.pre_link, entry, source, &target_path)
+                    && !self.run_hook(hook)
+                {
+                    return Ok(());
+                }
+                let post_link = self.hook("post_link", &entry.post_link, entry, source, &target_path);
+
+                if entry.privileged {
+                    // the parent may only be creatable as root, so leave it to the batch
+                    match target_path.parent() {
+                        Some(parent) if self.parents => {
+                            self.privileged.push(Op::CreateDir(parent.to_path_buf()))
+                        }
+                        _ => {
+                            if let Err(e) = ensure_parent(&target_path, false, None) {
+                                self.cannot_link(name_os_str, e);
+                                return Ok(());
+                            }
+                        }
+                    }
+
+                    self.made(entry, source, &target_path);
+                    self.privileged.push(Op::link(entry.link, source.clone(), target_path));
+                    self.post_privileged.extend(post_link);
+                    return Ok(());
+                }
+
+                // ensure parent directory exists before creating symlink
+                let dir_perms = self.cfg.settings.dir_perms;
+                if let Err(e) = ensure_parent(&target_path, self.parents, dir_perms) {
+                    self.cannot_link(name_os_str, e);
+                    return Ok(());
+                }
+
+                // create the symlink
+                if let Err(e) = entry.link.create(source, &target_path) {
+                    self.cannot_link(name_os_str, e);
+                    return Ok(());
+                }
+                self.made(entry, source, &target_path);
+                println!(
+                    "  {}",
+                    theme::ok(format!("Successfully created link for {:?}", name_os_str))
+                );
+                if let Some(hook) = post_link {
+                    self.run_hook(hook);
+                }
+            }
+            Err(e) => {
+                self.unreadable(name_os_str, &target_path, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn made(&mut self, entry: &ResolvedEntry, source: &Path, target: &Path) {
+        self.made
+            .push((entry.name.clone(), source.to_path_buf(), target.to_path_buf(), entry.link));
+    }
+
+    /// Inspects every link made in this run again, as `status` would.
+    fn verify(&mut self) {
+        let mut failed = 0;
+        for (name, source, target, kind) in &self.made {
+            let name = name.file_name().unwrap_or(name.as_os_str());
+            let reason = match status::inspect_target(&self.retry, source, target, *kind) {
+                State::Ok => continue,
+                State::Missing => "it is missing".to_string(),
+                State::Mismatch { points_to } => format!("it points to {points_to:?}"),
+                State::Conflict if *kind != LinkKind::Symlink => format!("it is not a {} to {source:?}", kind.name()),
+                State::Conflict => "it is not a symlink".to_string(),
+                State::SourceMissing => format!("the source {source:?} is missing"),
+                State::Error { error } => error,
+                State::Disabled => unreachable!("only enabled entries are linked"),
+            };
+            eprintln!("✖ The link for {:?} at {:?} did not take: {reason}", name, target);
+            failed += 1;
+        }
+
+        println!(
+            "\n[{}] Verified {} link(s), {} did not take.",
+            theme::info("INFO"),
+            self.made.len(),
+            failed
+        );
+        if failed > 0 {
+            self.all_ok = false;
+        }
+    }
+
+    /// Reports that `path` could not be read for the entry `name`. The entry is
+    /// left alone, the others are still fixed.
+    fn unreadable(&mut self, name: &OsStr, path: &Path, e: io::Error) {
+        eprintln!("✖ Cannot read {:?} for {:?}: {e}", path, name);
+        self.all_ok = false;
+    }
+
+    /// Reports that the link of the entry `name` could not be made, like a
+    /// permission denied on its directory. The others are still fixed.
+    fn cannot_link(&mut self, name: &OsStr, e: io::Error) {
+        eprintln!("✖ Cannot link {:?}: {e}", name);
+        self.all_ok = false;
+    }
+
+    /// Deals with the file in the way at `target` as `--resolve-conflicts` says.
+    fn resolve_conflict(&mut self, entry: &ResolvedEntry, source: &Path, target: PathBuf) -> Result<()> {
+        let name = entry.name.file_name().unwrap_or(entry.name.as_os_str());
+        let strategy = self.args.resolve_conflicts;
+        if strategy == ResolveConflicts::Report {
+            self.all_ok = false;
+            return Ok(());
+        }
+
+        if strategy == ResolveConflicts::Ours && fs::symlink_metadata(&target).is_ok_and(|m| m.is_symlink()) {
+            eprintln!("  {}", theme::error(format!("✖ Not adopting {:?}: it is a symlink", target)));
+            self.all_ok = false;
+            return Ok(());
+        }
+
+        // `ours` keeps the old source instead of what was at the target
+        let aside = match strategy {
+            ResolveConflicts::Ours => source,
+            _ => &target,
+        };
+        let backup = backup_path(aside);
+        println!(
+            "{}",
+            theme::action(match strategy {
+                ResolveConflicts::Ours => format!(
+                    "󰑓 Adopting {:?} as the source of {:?} (the old source goes to {:?})",
+                    target, name, backup
+                ),
+                _ => format!("󰑓 Moving {:?} aside to {:?}", target, backup),
+            })
+        );
+
+        if self.args.dry_run {
+            return Ok(());
+        }
+
+        if let Some(confirm_each) = &mut self.confirm_each
+            && !confirm_each.ask("  Go ahead?")?
+        {
+            println!("  Skipped.");
+            self.all_ok = false;
+            return Ok(());
+        }
+
+        if strategy == ResolveConflicts::Backup {
+            if entry.privileged {
+                self.privileged.push(Op::Move { from: target, to: backup });
+            } else if let Err(e) = copy::move_path(&target, &backup) {
+                eprintln!("✖ Cannot move {:?} aside: {e}", target);
+            }
+            // the link is still missing until the next run
+            self.all_ok = false;
+            return Ok(());
+        }
+
+        if let Some(hook) = self.hook("pre_link", &entry.pre_link, entry, source, &target)
+            && !self.run_hook(hook)
+        {
+            return Ok(());
+        }
+        let post_link = self.hook("post_link", &entry.post_link, entry, source, &target);
+
+        if entry.privileged {
+            self.privileged.push(Op::Move { from: aside.to_path_buf(), to: backup });
+            if strategy == ResolveConflicts::Ours {
+                self.privileged.push(Op::Move { from: target.clone(), to: source.to_path_buf() });
+            }
+            self.made(entry, source, &target);
+            self.privileged.push(Op::link(entry.link, source.to_path_buf(), target));
+            self.post_privileged.extend(post_link);
+            return Ok(());
+        }
+
+        let resolved = copy::move_path(aside, &backup)
+            .and_then(|()| match strategy {
+                ResolveConflicts::Ours => copy::move_path(&target, source),
+                _ => Ok(()),
+            })
+            .and_then(|()| entry.link.create(source, &target));
+        if let Err(e) = resolved {
+            self.cannot_link(name, e);
+            return Ok(());
+        }
+        self.made(entry, source, &target);
+        println!(
+            "  {}",
+            theme::ok(format!("Successfully created link for {:?}", name))
+        );
+        if let Some(hook) = post_link {
+            self.run_hook(hook);
+        }
+
+        Ok(())
+    }
+
+    /// The `command` of `entry` for linking `source` at `target`, unless hooks
+    /// are turned off.
+    fn hook(
+        &self,
+        kind: &'static str,
+        command: &Option<String>,
+        entry: &ResolvedEntry,
+        source: &Path,
+        target: &Path,
+    ) -> Option<Hook> {
+        let command = command.as_ref().filter(|_| !self.args.no_hooks)?;
+        Some(Hook {
+            kind,
+            command: command.clone(),
+            name: entry.name.clone(),
+            source: source.to_path_buf(),
+            target: target.to_path_buf(),
+        })
+    }
+
+    /// Runs `hook`, reporting a failure. Returns whether it succeeded.
+    fn run_hook(&mut self, hook: Hook) -> bool {
+        let name = hook.name.file_name().unwrap_or(hook.name.as_os_str());
+        println!("[{}] Running {} of {:?}: {}", theme::info("INFO"), hook.kind, name, hook.command);
+
+        match hook.run() {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("✖ {} of {:?} failed: {e}", hook.kind, name);
+                self.all_ok = false;
+                false
+            }
+        }
+    }
+}
+
+/// Prints why the link at `target` is not considered to point at `source`:
+/// the link as stored and both sides with relative parts and symlinks resolved.
+fn explain_mismatch(target: &Path, link: &Path, source: &Path) {
+    let resolve = |path: PathBuf| fs::canonicalize(&path).unwrap_or_else(|_| path.clean());
+    let link_resolved = resolve(target.parent().unwrap_or(Path::new("/")).join(link));
+    let source_resolved = resolve(source.to_path_buf());
+
+    eprintln!("    link:     {:?}", link);
+    eprintln!("      resolves to {:?}", link_resolved);
+    eprintln!("    expected: {:?}", source);
+    eprintln!("      resolves to {:?}", source_resolved);
+    if link_resolved == source_resolved {
+        eprintln!("    Both lead to the same file, only the link is written differently; `--repoint` rewrites it.");
+    }
+}
+
+/// Pairs of `(inner, outer)` targets where `outer` is a directory above `inner`.
+///
+/// Linking `outer` turns it into a symlink into the dotfiles root, so `inner`
+/// would then be created inside the root instead of where it was meant to go.
+fn nested_targets<'a>(targets: impl IntoIterator<Item = &'a PathBuf>) -> Vec<(PathBuf, PathBuf)> {
+    let targets: HashSet<&Path> = targets.into_iter().map(PathBuf::as_path).collect();
+    let mut nested = Vec::new();
+    for inner in &targets {
+        if let Some(outer) = inner.ancestors().skip(1).find(|dir| targets.contains(dir)) {
+            nested.push((inner.to_path_buf(), outer.to_path_buf()));
+        }
+    }
+    nested.sort();
+    nested
+}
+
+/// Whether `target` lies inside the dotfiles root or contains it. Linking such a
+/// target would put a link to the root into the root, or replace the root's parent.
+fn overlaps_root(target: &Path, root: &Path) -> bool {
+    let target = resolve_parent(target);
+    target.starts_with(root) || root.starts_with(&target)
+}
+
+/// The directory above `path` that is a symlink into `root`, as left behind when
+/// a directory entry that was linked as a whole is folded.
+fn linked_into_root(path: &Path, root: &Path) -> Option<PathBuf> {
+    symlinked_ancestor(path).filter(|dir| fs::canonicalize(dir).is_ok_and(|dir| dir.starts_with(root)))
+}
+
+/// The deepest existing directory above `path` that is a symlink, if any.
+fn symlinked_ancestor(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .skip(1)
+        .find(|dir| fs::symlink_metadata(dir).is_ok_and(|m| m.file_type().is_symlink()))
+        .map(Path::to_path_buf)
+}
+
+/// Resolves symlinks in the parent directories of `path` but not in its last
+/// component, so a symlinked source is judged by where it lives, not where it points.
+fn resolve_parent(path: &Path) -> PathBuf {
+    let path = path.clean();
+    match (path.parent().map(fs::canonicalize), path.file_name()) {
+        (Some(Ok(parent)), Some(name)) => parent.join(name),
+        _ => path,
+    }
+}
+
+fn check(cfg: &Config) -> Result<bool> {
+    // without a checkout of the root (CI, other machines) the config itself can still be linted
+    let (root, on_disk) = match cfg.get_root() {
+        Ok(root) => (root, true),
+        Err(Error::RootNotFound(root)) => {
+            let root = root.clean();
+            println!(
+                "[{}] Dotfiles root {:?} not present, skipping on-disk checks.",
+                theme::info("INFO"),
+                root
+            );
+            (root, false)
+        }
+        Err(e) => return Err(e),
+    };
+
+    let mut problems = Vec::new();
+    let mut targets: HashMap<PathBuf, PathBuf> = HashMap::new();
+    let mut count = 0;
+    let mut disabled = 0;
+
+    for entry in cfg.entries_under(&root)? {
+        if !entry.enabled {
+            disabled += 1;
+            continue;
+        }
+
+        count += 1;
+
+        let source = if on_disk {
+            resolve_parent(&entry.source)
+        } else {
+            entry.source.clone()
+        };
+
+        if !source.starts_with(&root) {
+            problems.push(format!("{:?} is outside the dotfiles root {:?}", entry.name, root));
+        } else if on_disk && fs::symlink_metadata(&entry.source).is_err() {
+            problems.push(format!("source {:?} does not exist", entry.source));
+        }
+
+        match cfg.resolve_target(&entry.target) {
+            Ok(target) => {
+                if entry.folded
+                    && let Some(ancestor) = linked_into_root(&target, &root)
+                {
+                    let problem = format!(
+                        "{:?} is a symlink into the dotfiles root, but folded entries link the files inside it",
+                        ancestor
+                    );
+                    if !problems.contains(&problem) {
+                        problems.push(problem);
+                    }
+                } else if overlaps_root(&target, &root) {
+                    problems.push(format!(
+                        "target {:?} of {:?} overlaps the dotfiles root {:?}",
+                        target, entry.name, root
+                    ));
+                }
+
+                if let Some(other) = targets.insert(target.clone(), entry.name.clone()) {
+                    problems.push(format!(
+                        "{:?} and {:?} both link to {:?}",
+                        other, entry.name, target
+                    ));
+                }
+            }
+            Err(e) => problems.push(format!("target of {:?} cannot be resolved: {e}", entry.name)),
+        }
+    }
+
+    if on_disk && link::case_insensitive(&root) {
+        problems.extend(case_collisions(cfg.entries_under(&root)?.map(|entry| entry.source)));
+    }
+
+    for (inner, outer) in nested_targets(targets.keys()) {
+        problems.push(format!(
+            "target {:?} of {:?} is inside target {:?} of {:?}; once that is linked, \
+             the inner link would be created inside the dotfiles root",
+            inner, targets[&inner], outer, targets[&outer]
+        ));
+    }
+
+    for problem in &problems {
+        eprintln!("✖ {problem}");
+    }
+
+    if disabled > 0 {
+        println!("[{}] Skipped {disabled} disabled entries.", theme::info("INFO"));
+    }
+
+    if problems.is_empty() {
+        println!("✅ {count} entries checked, no problems found.");
+    } else {
+        println!("❌ {} problem(s) found in {count} entries.", problems.len());
+    }
+
+    Ok(problems.is_empty())
+}
+
+/// Problems for `sources` that are different entries but the same file in a
+/// root whose filesystem ignores case.
+fn case_collisions(sources: impl IntoIterator<Item = PathBuf>) -> Vec<String> {
+    let mut seen: HashMap<String, PathBuf> = HashMap::new();
+    let mut problems = Vec::new();
+    for source in sources {
+        let key = source.to_string_lossy().to_lowercase();
+        match seen.get(&key) {
+            Some(other) if *other != source => {
+                // an entry with several targets comes up once per target
+                let problem = format!(
+                    "sources {:?} and {:?} only differ in case, the filesystem of the dotfiles root treats them as one file",
+                    other, source
+                );
+                if !problems.contains(&problem) {
+                    problems.push(problem);
+                }
+            }
+            Some(_) => {}
+            None => {
+                seen.insert(key, source);
+            }
+        }
+    }
+
+    problems
+}
+
+fn status(
+    cfg: &Config,
+    count: bool,
+    json_lines: bool,
+    report_file: Option<&Path>,
+    filter_state: &[StateName],
+    explain: bool,
+) -> Result<bool> {
+    let mut entries = Vec::new();
+    let summary = status::inspect(cfg, |entry| {
+        if report_file.is_some() {
+            entries.push(entry.clone());
+        }
+
+        // the summary and exit status still cover every entry
+        if count || !filter_state.is_empty() && !filter_state.contains(&entry.state.name()) {
+            return;
+        }
+
+        if json_lines {
+            println!("{}", json_line(&Record::Entry(entry)));
+            return;
+        }
+
+        let name = entry.name.file_name().unwrap_or(entry.name.as_os_str());
+        let line = format!("{:?} -> {:?}", name, entry.target);
+        let why = if explain {
+            let enabled = !matches!(entry.state, State::Disabled);
+            format!(" ({})", entry.origin.explain(enabled))
+        } else {
+            String::new()
+        };
+        match &entry.state {
+            State::Ok => println!("{}", format!("󰄬 {line} [ok]{why}").bold()),
+            State::Missing => println!("{}", theme::action(format!("󰜺 {line} [missing]{why}"))),
+            State::Conflict => println!("{}", theme::error(format!("✖ {line} [conflict]{why}"))),
+            State::Mismatch { points_to } => println!(
+                "{}",
+                theme::warning(format!("⚠ {line} [mismatch, points to {points_to:?}]{why}"))
+            ),
+            State::SourceMissing => println!(
+                "{}",
+                theme::error(format!("✖ {line} [source {:?} missing]{why}", entry.source))
+            ),
+            State::Disabled => println!("- {line} [disabled]{why}"),
+            State::Error { error } => {
+                println!("{}", theme::error(format!("✖ {line} [error: {error}]{why}")))
+            }
+        }
+    })?;
+
+    if let Some(path) = report_file {
+        Report {
+            entries: &entries,
+            summary: &summary,
+        }
+        .write(path)?;
+    }
+
+    if json_lines {
+        println!("{}", json_line(&Record::Summary(&summary)));
+    } else if count {
+        println!("{summary}");
+    } else {
+        println!("\n{summary}");
+    }
+
+    Ok(summary.all_ok())
+}
+
+fn tree(cfg: &Config, json: bool) -> Result<bool> {
+    let mut entries = Vec::new();
+    let summary = status::inspect(cfg, |entry| entries.push(entry.clone()))?;
+    let nodes = tree::build(&entries);
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&nodes).expect("failed to serialize tree")
+        );
+    } else {
+        tree::print(&nodes);
+        println!("\n{summary}");
+    }
+
+    Ok(summary.all_ok())
+}
+
+fn disk_usage(cfg: &Config, json: bool) -> Result<bool> {
+    let stats = Stats::collect(cfg)?;
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&stats).expect("failed to serialize stats")
+        );
+    } else {
+        stats.print();
+    }
+
+    Ok(true)
+}
+
+/// Prints the links of the entry `query` names, matched like `unlink` does by
+/// its source or one of its targets, or by its name as written in the config.
+fn resolve(cfg: &Config, query: &str, json: bool) -> Result<bool> {
+    let query_path = Path::new(query);
+    // a link that is broken or points elsewhere is only found without resolving it
+    let paths = [
+        fs::canonicalize(query_path).ok(),
+        Some(std::env::current_dir()?.join(query_path).clean()),
+    ];
+    let retry = Retry::new(cfg.settings.retries, cfg.settings.retry_delay_ms);
+
+    let mut found = false;
+    for entry in cfg.entries()? {
+        let target = cfg.resolve_target(&entry.target).ok();
+        let matches = entry.name == query_path.clean()
+            || entry.alias.as_deref() == Some(query)
+            || paths.contains(&Some(entry.source.clone()))
+            || target.is_some() && paths.contains(&target);
+        if !matches {
+            continue;
+        }
+
+        let status = status::inspect_entry(cfg, &retry, entry);
+        let link = target.and_then(|target| fs::read_link(target).ok());
+        if json {
+            let resolved = Resolved { status: &status, link };
+            println!("{}", serde_json::to_string(&resolved).expect("failed to serialize entry"));
+        } else {
+            if found {
+                println!();
+            }
+            println!("name:   {:?}", status.name);
+            println!("source: {:?}", status.source);
+            println!("target: {:?}", status.target);
+            let state = match &status.state {
+                State::Ok => "ok".to_string(),
+                State::Missing => "missing".to_string(),
+                State::Conflict => "conflict".to_string(),
+                State::Mismatch { points_to } => format!("mismatch, points to {points_to:?}"),
+                State::SourceMissing => "source missing".to_string(),
+                State::Disabled => "disabled".to_string(),
+                State::Error { error } => format!("error: {error}"),
+            };
+            println!("state:  {state}");
+            if let Some(link) = link {
+                println!("link:   {link:?}");
+            }
+        }
+        found = true;
+    }
+
+    if !found {
+        return Err(Error::NoMatchingEntry(query.to_string()));
+    }
+    Ok(true)
+}
+
+fn json_line(record: &Record) -> String {
+    serde_json::to_string(record).expect("failed to serialize status record")
+}
+
+fn relocate(cfg_path: &Path, cfg: &mut Config, new_root: &Path, yes: bool) -> Result<bool> {
+    let old_root = cfg.configured_root()?.clean();
+    let old_root = fs::canonicalize(&old_root).unwrap_or(old_root);
+    let new_root = fs::canonicalize(new_root)?;
+
+    // re-keyed entries of the main config (in every profile), and links that still point into the old root
+    let mut rekeyed = Vec::new();
+    let profile_keys = cfg.profiles.values().flat_map(|p| p.entries.keys());
+    for source in cfg.entries.keys().chain(profile_keys) {
+        if let Ok(rest) = source.strip_prefix(&old_root) {
+            rekeyed.push((source.clone(), new_root.join(rest)));
+        }
+    }
+
+    for (source, (file, _)) in &cfg.included {
+        if source.starts_with(&old_root) {
+            eprintln!(
+                "{} {:?} is defined in included config {:?}, update it there.",
+                theme::warning("Warning:"),
+                source,
+                file
+            );
+        }
+    }
+
+    let mut links = Vec::new();
+    for (_, entry) in cfg.all_entries() {
+        for target in &entry.targets {
+            let Ok(target) = cfg.resolve_target(target) else {
+                continue;
+            };
+
+            if let Ok(link) = fs::read_link(&target)
+                && let Ok(rest) = link.strip_prefix(&old_root)
+            {
+                links.push((target, new_root.join(rest), entry.privileged));
+            }
+        }
+    }
+
+    println!(
+        "[{}] Relocating {} -> {}",
+        theme::info("INFO"),
+        theme::path(format!("{:?}", old_root.display())),
+        theme::path(format!("{:?}", new_root.display()))
+    );
+    println!("  - {} entries to update, {} links to repoint", rekeyed.len(), links.len());
+
+    if !yes {
+        if !prompt::is_interactive() {
+            eprintln!("stdin is not a terminal, pass --yes to relocate.");
+            return Ok(false);
+        }
+
+        if !prompt::confirm("Continue?")? {
+            return Ok(false);
+        }
+    }
+
+    let mut privileged = Batch::default();
+    for (target, new_link, is_privileged) in links {
+        println!(
+            "  - Repointing {} -> {}",
+            theme::path(format!("{:?}", target.display())),
+            theme::path(format!("{:?}", new_link.display()))
+        );
+
+        if is_privileged {
+            privileged.push(Op::Remove(target.clone()));
+            privileged.push(Op::Symlink {
+                source: new_link,
+                target,
+            });
+        } else {
+            fs::remove_file(&target)?;
+            fs::symlink(&new_link, &target)?;
+        }
+    }
+    privileged.run()?;
+
+    let profile_maps = cfg.profiles.values_mut().map(|p| &mut p.entries);
+    for entries in std::iter::once(&mut cfg.entries).chain(profile_maps) {
+        for (old, new) in &rekeyed {
+            if let Some(entry) = entries.remove(old) {
+                entries.insert(new.clone(), entry);
+            }
+        }
+    }
+
+    if let Some(local) = cfg.local.as_ref().filter(|local| local.settings.dotlink_root.is_some()) {
+        println!(
+            "[{}] The root comes from {:?}, remember to update it to {:?}.",
+            theme::info("INFO"),
+            local.path,
+            new_root
+        );
+    } else if cfg.settings.dotlink_root.is_some() {
+        cfg.settings.dotlink_root = Some(new_root.clone());
+    } else if let Some((marker, _)) = &cfg.root_marker {
+        println!(
+            "[{}] The root comes from {:?}, remember to update it to {:?}.",
+            theme::info("INFO"),
+            marker,
+            new_root
+        );
+    } else if cfg.git_root.is_some() && std::env::var_os(cfg.root_env()).is_none() {
+        println!(
+            "[{}] The root comes from git, run dotlink from the new checkout from now on.",
+            theme::info("INFO")
+        );
+    } else {
+        println!(
+            "[{}] The root comes from `{}`, remember to update it to {:?}.",
+            theme::info("INFO"),
+            cfg.root_env(),
+            new_root
+        );
+    }
+
+    save_config(cfg, cfg_path)?;
+    println!("✅ Relocation complete.");
+
+    Ok(true)
+}
+
+fn repair_config(cfg_path: &Path, cfg: &mut Config, yes: bool) -> Result<bool> {
+    let changes = repair::plan(cfg)?;
+    if changes.is_empty() {
+        println!("✅ The config matches the dotfiles root and links.");
+        return Ok(true);
+    }
+
+    println!("[{}] Proposed changes to {:?}:", theme::info("INFO"), cfg_path);
+    for change in &changes {
+        println!("  - {}", theme::action(change));
+    }
+
+    if !yes {
+        if !prompt::is_interactive() {
+            eprintln!("stdin is not a terminal, pass --yes to apply the changes.");
+            return Ok(false);
+        }
+
+        if !prompt::confirm("Apply?")? {
+            return Ok(false);
+        }
+    }
+
+    for change in changes {
+        match change {
+            Change::Add { source, target } => {
+                cfg.entries.insert(expand::escape(&source), Entry::new(expand::escape(&target)));
+            }
+            Change::Rekey { old, new } => {
+                let entries = cfg.entries_containing(&old);
+                if let Some(entry) = entries.remove(&old) {
+                    entries.insert(expand::escape(&new), entry);
+                }
+            }
+            Change::Remove { key } => {
+                cfg.entries_containing(&key).remove(&key);
+            }
+        }
+    }
+
+    save_config(cfg, cfg_path)?;
+    println!("✅ Config updated.");
+    Ok(true)
+}
+
+fn check_orphans(
+    cfg_path: &Path,
+    cfg: &mut Config,
+    dirs: &[PathBuf],
+    max_depth: usize,
+    adopt: bool,
+) -> Result<bool> {
+    let dirs = if dirs.is_empty() {
+        vec![expand_path(Path::new("~"))?]
+    } else {
+        let cwd = std::env::current_dir()?;
+        dirs.iter().map(|dir| cwd.join(dir).clean()).collect()
+    };
+
+    let orphans = orphans::find(cfg, &dirs, max_depth)?;
+    if orphans.is_empty() {
+        println!("✅ No unmanaged symlinks into the dotfiles root.");
+        return Ok(true);
+    }
+
+    for orphan in &orphans {
+        println!(
+            "  - {} -> {}",
+            theme::path(format!("{:?}", orphan.target.display())),
+            theme::path(format!("{:?}", orphan.source.display()))
+        );
+    }
+
+    if !adopt {
+        println!(
+            "{}",
+            theme::error(format!("✖ {} symlink(s) into the dotfiles root without an entry.", orphans.len()))
+        );
+        return Ok(false);
+    }
+
+    let root = cfg.get_root()?;
+    let mut all_ok = true;
+    for orphan in orphans {
+        let target = cfg.portable_target(&orphan.target);
+        all_ok &= orphans::adopt(cfg, &root, orphan.source, target);
+    }
+
+    save_config(cfg, cfg_path)?;
+    println!("✅ Config updated.");
+    Ok(all_ok)
+}
+
+/// Prints the links `fix` (with `--repoint` if `repoint`) would make as a
+/// JSON `Plan`. Entries `fix` would refuse or could not help, such as
+/// conflicts, are reported and left out, and make it exit with status 1.
+fn print_plan(cfg: &Config, repoint: bool) -> Result<bool> {
+    let root = cfg.get_root()?;
+    let retry = Retry::new(cfg.settings.retries, cfg.settings.retry_delay_ms);
+
+    let targets: HashSet<PathBuf> = cfg
+        .entries_under(&root)?
+        .filter(|entry| entry.enabled)
+        .filter_map(|entry| cfg.resolve_target(&entry.target).ok())
+        .collect();
+    let nested: HashMap<_, _> = nested_targets(&targets).into_iter().collect();
+
+    let mut actions = Vec::new();
+    let mut all_ok = true;
+    for entry in cfg.entries_under(&root)?.filter(|entry| entry.enabled) {
+        let target = match cfg.resolve_target(&entry.target) {
+            Ok(target) if nested.contains_key(&target) || overlaps_root(&target, &root) => {
+                eprintln!("✖ {:?}: fix refuses to link {:?}, left out of the plan", entry.name, target);
+                all_ok = false;
+                continue;
+            }
+            Ok(target) => target,
+            Err(e) => {
+                eprintln!("✖ Cannot resolve target for {:?}: {e}", entry.name);
+                all_ok = false;
+                continue;
+            }
+        };
+
+        match status::inspect_target(&retry, &entry.source, &target, entry.link) {
+            State::Ok => {}
+            State::Missing => actions.push(plan::Action::Link {
+                source: entry.source,
+                target,
+                kind: entry.link,
+                privileged: entry.privileged,
+            }),
+            State::Mismatch { points_to } if repoint => actions.push(plan::Action::Repoint {
+                source: entry.source,
+                target,
+                kind: entry.link,
+                privileged: entry.privileged,
+                points_to,
+            }),
+            state => {
+                let problem = match state {
+                    State::Mismatch { points_to } => format!("it points to {points_to:?} (see --repoint)"),
+                    State::SourceMissing => format!("its source {:?} is missing", entry.source),
+                    State::Error { error } => error,
+                    _ => "something else is in the way".to_string(),
+                };
+                eprintln!("✖ {:?} -> {:?}: {problem}, left out of the plan", entry.name, target);
+                all_ok = false;
+            }
+        }
+    }
+
+    let plan = plan::Plan {
+        version: plan::PLAN_VERSION,
+        root,
+        actions,
+    };
+    println!("{}", serde_json::to_string_pretty(&plan).expect("failed to serialize plan"));
+    Ok(all_ok)
+}
+
+/// Carries out a plan written by `print_plan`, after checking that none of its
+/// files changed since. With `skip_drifted` the changed ones are left out
+/// instead of refusing the whole plan.
+fn apply_from(cfg: &Config, path: &Path, skip_drifted: bool, parents: bool) -> Result<bool> {
+    let contents = if is_stdin(path) {
+        io::read_to_string(io::stdin())?
+    } else {
+        fs::read_to_string(path)?
+    };
+    let invalid = |reason: String| Error::InvalidPlan {
+        path: path.to_path_buf(),
+        reason,
+    };
+    let plan: plan::Plan = serde_json::from_str(&contents).map_err(|e| invalid(e.to_string()))?;
+    if plan.version != plan::PLAN_VERSION {
+        return Err(invalid(format!(
+            "version {} is not supported, this dotlink reads version {}",
+            plan.version,
+            plan::PLAN_VERSION
+        )));
+    }
+
+    // the actions name their paths in full, so they don't depend on the config
+    if cfg.get_root().is_ok_and(|root| root != plan.root) {
+        eprintln!(
+            "  {} The plan was made for the dotfiles root {:?}, not {:?}",
+            theme::warning("Warning:"),
+            plan.root,
+            cfg.get_root()?
+        );
+    }
+
+    let mut drifted = 0;
+    let mut actions = Vec::new();
+    for action in plan.actions {
+        match action.drift() {
+            Some(drift) => {
+                eprintln!("{}", theme::warning(format!("⚠ Drift: {drift}")));
+                drifted += 1;
+            }
+            None => actions.push(action),
+        }
+    }
+
+    if drifted > 0 && !skip_drifted {
+        println!(
+            "{}",
+            theme::error(format!(
+                "✖ {drifted} action(s) no longer match the files, nothing was changed. \
+                 Make a new plan, or use --skip-drifted to apply the rest."
+            ))
+        );
+        return Ok(false);
+    }
+
+    let mut privileged = Batch::default();
+    for action in &actions {
+        let verb = match action {
+            plan::Action::Link { .. } => "Linking",
+            plan::Action::Repoint { .. } => "Repointing",
+        };
+        println!(
+            "  - {verb} {} -> {}",
+            theme::path(format!("{:?}", action.source().display())),
+            theme::path(format!("{:?}", action.target().display()))
+        );
+        action.apply(parents, cfg.settings.dir_perms, &mut privileged)?;
+    }
+    privileged.run()?;
+
+    if drifted > 0 {
+        println!("❌ Applied {} action(s), left out {drifted} that drifted.", actions.len());
+    } else {
+        println!("✅ Applied {} action(s).", actions.len());
+    }
+    Ok(drifted == 0)
+}
+
+fn migrate_config(cfg_path: &Path, cfg: &Config) -> Result<bool> {
+    let current = fs::read_to_string(cfg_path)?;
+    let (migrated, changes) = migrate::migrate(&current);
+    if changes.is_empty() {
+        println!("✅ {cfg_path:?} is already at version {CONFIG_VERSION}.");
+        return Ok(true);
+    }
+
+    // a step that broke the file must not replace a working config
+    toml::from_str::<Config>(&migrated).map_err(|source| Error::ConfigParse {
+        path: cfg_path.to_path_buf(),
+        source,
+    })?;
+
+    for change in &changes {
+        println!("  - {}", theme::action(change));
+    }
+
+    config::write_config(cfg_path, migrated, cfg.backups_kept())?;
+    println!("✅ Migrated {cfg_path:?} to version {CONFIG_VERSION}.");
+    Ok(true)
+}
+
+fn config_backups(cfg_path: &Path) -> Result<bool> {
+    let backups = backup::list(cfg_path)?;
+    if backups.is_empty() {
+        println!("[{}] No backups of {:?} yet.", theme::info("INFO"), cfg_path);
+        return Ok(true);
+    }
+
+    for backup in &backups {
+        println!("{:<19} {}", backup.id, theme::path(format!("{:?}", backup.path.display())));
+    }
+    Ok(true)
+}
+
+fn config_restore(cfg_path: &Path, id: &str) -> Result<bool> {
+    let Some(backup) = backup::list(cfg_path)?.into_iter().find(|backup| backup.id == id) else {
+        eprintln!("✖ No backup {id:?} of {:?}, see `dotlink config-backups`.", cfg_path);
+        return Ok(false);
+    };
+
+    // the current config may be what is broken, so its setting is only used if it loads
+    let keep = load_cfg(cfg_path).map_or(backup::KEEP, |cfg| cfg.backups_kept());
+    config::write_config(cfg_path, fs::read_to_string(&backup.path)?, keep)?;
+    println!("✅ Restored {:?} from {}.", cfg_path, backup.id);
+    Ok(true)
+}
+
+fn format_config(cfg_path: &Path, cfg: &mut Config, check: bool) -> Result<bool> {
+    canonical::normalize(cfg)?;
+
+    let current = fs::read_to_string(cfg_path)?;
+    let formatted = config::to_toml(cfg);
+    if current == formatted {
+        println!("✅ {cfg_path:?} is already formatted.");
+        return Ok(true);
+    }
+
+    if check {
+        println!("{}", theme::error(format!("✖ {cfg_path:?} is not formatted:")));
+        let diff = canonical::diff(&current, &formatted);
+        let changed = |i: usize| diff.get(i).is_some_and(|line| !matches!(line, canonical::Line::Same(_)));
+        for (i, line) in diff.iter().enumerate() {
+            match line {
+                // one line of context around every change
+                canonical::Line::Same(line) if changed(i + 1) || i > 0 && changed(i - 1) => println!(" {line}"),
+                canonical::Line::Same(_) => {}
+                canonical::Line::Removed(line) => println!("{}", theme::error(format!("-{line}"))),
+                canonical::Line::Added(line) => println!("{}", theme::ok(format!("+{line}"))),
+            }
+        }
+        return Ok(false);
+    }
+
+    save_config(cfg, cfg_path)?;
+    println!("✅ Formatted {cfg_path:?}.");
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory for one test, named after it.
+    fn scratch(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dotlink-{}-{test}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn pattern(dir: &Path, rest: &str) -> String {
+        format!("{}/{rest}", dir.display())
+    }
+
+    #[test]
+    fn overlapping_globs_give_each_path_once() {
+        let dir = scratch("overlapping-globs");
+        std::fs::create_dir_all(dir.join(".config/nvim")).unwrap();
+        std::fs::create_dir_all(dir.join(".config/tmux")).unwrap();
+
+        let patterns = [
+            pattern(&dir, ".config/{nvim,tmux}"),
+            pattern(&dir, ".config/*"),
+        ];
+        let mut paths = unique_paths(&patterns, false).unwrap();
+        paths.sort();
+        assert_eq!(paths, [dir.join(".config/nvim"), dir.join(".config/tmux")]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn negation_removes_matches_of_other_patterns() {
+        let dir = scratch("negation");
+        for name in ["nvim", "secret", "tmux"] {
+            std::fs::create_dir_all(dir.join(".config").join(name)).unwrap();
+        }
+
+        let patterns = [
+            pattern(&dir, ".config/*"),
+            format!("!{}", pattern(&dir, ".config/secret")),
+        ];
+        let mut paths = resolve_patterns(&patterns).unwrap();
+        paths.sort();
+        assert_eq!(paths, [dir.join(".config/nvim"), dir.join(".config/tmux")]);
+
+        // the negation applies wherever it appears in the list
+        let patterns = [
+            format!("!{}", pattern(&dir, ".config/{nvim,tmux}")),
+            pattern(&dir, ".config/*"),
+        ];
+        assert_eq!(
+            resolve_patterns(&patterns).unwrap(),
+            [dir.join(".config/secret")]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn only_negations_match_nothing() {
+        let dir = scratch("only-negations");
+        std::fs::create_dir_all(dir.join(".config/nvim")).unwrap();
+
+        let patterns = [format!("!{}", pattern(&dir, ".config/*"))];
+        assert!(resolve_patterns(&patterns).unwrap().is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Runs dotlink with the config at `cfg` and `args`, as from the command line.
+    fn dotlink(cfg: &Path, args: &[&str]) -> Result<bool> {
+        let cfg = cfg.to_str().unwrap();
+        run(Cli::try_parse_from(["dotlink", "-c", cfg].iter().chain(args)).unwrap())
+    }
+
+    /// A dotfiles root with `app.conf` ("source") linked from `home/app.conf`,
+    /// where a regular file ("existing") is in the way. Returns the config.
+    fn conflict(dir: &Path) -> PathBuf {
+        std::fs::create_dir_all(dir.join("root")).unwrap();
+        std::fs::create_dir_all(dir.join("home")).unwrap();
+        std::fs::write(dir.join("root/app.conf"), "source").unwrap();
+        std::fs::write(dir.join("home/app.conf"), "existing").unwrap();
+
+        let cfg = dir.join("Link.toml");
+        let toml = format!(
+            "[settings]\ndotlink_root = {root:?}\n\n[entries]\n{source:?} = {target:?}\n",
+            root = dir.join("root"),
+            source = dir.join("root/app.conf"),
+            target = dir.join("home/app.conf"),
+        );
+        std::fs::write(&cfg, toml).unwrap();
+        cfg
+    }
+
+    fn read(path: PathBuf) -> String {
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn conflicts_are_only_reported_by_default() {
+        let dir = scratch("conflict-report");
+        let cfg = conflict(&dir);
+
+        assert!(!dotlink(&cfg, &["fix"]).unwrap());
+        assert_eq!(read(dir.join("home/app.conf")), "existing");
+        assert!(!dir.join("home/app.conf.bak").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn theirs_moves_the_file_aside_and_links() {
+        let dir = scratch("conflict-theirs");
+        let cfg = conflict(&dir);
+
+        assert!(dotlink(&cfg, &["fix", "--resolve-conflicts", "theirs"]).unwrap());
+        let target = dir.join("home/app.conf");
+        assert_eq!(
+            std::fs::read_link(&target).unwrap(),
+            dir.join("root/app.conf")
+        );
+        assert_eq!(read(target), "source");
+        assert_eq!(read(dir.join("home/app.conf.bak")), "existing");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ours_adopts_the_file_and_keeps_the_old_source() {
+        let dir = scratch("conflict-ours");
+        let cfg = conflict(&dir);
+
+        assert!(dotlink(&cfg, &["fix", "--resolve-conflicts", "ours"]).unwrap());
+        let target = dir.join("home/app.conf");
+        assert_eq!(
+            std::fs::read_link(&target).unwrap(),
+            dir.join("root/app.conf")
+        );
+        assert_eq!(read(dir.join("root/app.conf")), "existing");
+        assert_eq!(read(dir.join("root/app.conf.bak")), "source");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn backup_only_moves_the_file_aside() {
+        let dir = scratch("conflict-backup");
+        let cfg = conflict(&dir);
+
+        // the link is still missing, so the run is not clean
+        assert!(!dotlink(&cfg, &["fix", "--resolve-conflicts", "backup"]).unwrap());
+        assert!(std::fs::symlink_metadata(dir.join("home/app.conf")).is_err());
+        assert_eq!(read(dir.join("home/app.conf.bak")), "existing");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn taken_backup_names_are_numbered() {
+        let dir = scratch("conflict-numbered");
+        let cfg = conflict(&dir);
+        std::fs::write(dir.join("home/app.conf.bak"), "older").unwrap();
+
+        assert!(dotlink(&cfg, &["fix", "--resolve-conflicts", "theirs"]).unwrap());
+        assert_eq!(read(dir.join("home/app.conf.bak")), "older");
+        assert_eq!(read(dir.join("home/app.conf.bak-2")), "existing");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn directories_in_the_way_are_moved_aside_whole() {
+        let dir = scratch("conflict-dir");
+        let cfg = conflict(&dir);
+        std::fs::remove_file(dir.join("home/app.conf")).unwrap();
+        std::fs::create_dir_all(dir.join("home/app.conf/sub")).unwrap();
+        std::fs::create_dir(dir.join("home/app.conf.bak")).unwrap();
+
+        assert!(dotlink(&cfg, &["fix", "--resolve-conflicts", "theirs"]).unwrap());
+        assert!(dir.join("home/app.conf.bak-2/sub").is_dir());
+        assert_eq!(read(dir.join("home/app.conf")), "source");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn numbered_names() {
+        let name = |name: &str, is_dir| numbered_name(OsStr::new(name), 2, is_dir);
+        assert_eq!(name("init.lua", false), Path::new("init-2.lua"));
+        assert_eq!(name(".bashrc", false), Path::new(".bashrc-2"));
+        assert_eq!(name("nvim.d", true), Path::new("nvim.d-2"));
+        assert_eq!(name("nvim", true), Path::new("nvim-2"));
+    }
+
+    /// A dotfiles root with `app.conf` recorded for `home/gone/app.conf`, a
+    /// directory that no longer exists. Returns the config.
+    fn parent_gone(dir: &Path) -> PathBuf {
+        std::fs::create_dir_all(dir.join("root")).unwrap();
+        std::fs::write(dir.join("root/app.conf"), "source").unwrap();
+
+        let cfg = dir.join("Link.toml");
+        let toml = format!(
+            "[settings]\ndotlink_root = {root:?}\n\n[entries]\n{source:?} = {target:?}\n",
+            root = dir.join("root"),
+            source = dir.join("root/app.conf"),
+            target = dir.join("home/gone/app.conf"),
+        );
+        std::fs::write(&cfg, toml).unwrap();
+        cfg
+    }
+
+    #[test]
+    fn unlink_recreates_a_missing_parent() {
+        let dir = scratch("unlink-parent");
+        let cfg = parent_gone(&dir);
+        let source = dir.join("root/app.conf");
+
+        assert!(dotlink(&cfg, &["unlink", source.to_str().unwrap()]).unwrap());
+        assert_eq!(read(dir.join("home/gone/app.conf")), "source");
+        assert!(!source.exists());
+        assert!(!read(cfg).contains("app.conf"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unlink_leaves_a_missing_parent_with_no_parents() {
+        let dir = scratch("unlink-no-parents");
+        let cfg = parent_gone(&dir);
+        let source = dir.join("root/app.conf");
+
+        let args = [
+            "--no-parents",
+            "unlink",
+            "--keep-going",
+            source.to_str().unwrap(),
+        ];
+        assert!(!dotlink(&cfg, &args).unwrap());
+        assert!(!dir.join("home/gone").exists());
+        assert_eq!(read(source), "source");
+        assert!(read(cfg).contains("app.conf"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn adding_many_files_keeps_the_config_from_before() {
+        let dir = scratch("add-many-backup");
+        std::fs::create_dir_all(dir.join("root")).unwrap();
+        std::fs::create_dir_all(dir.join("home")).unwrap();
+        for n in 0..backup::KEEP + 2 {
+            std::fs::write(dir.join(format!("home/f{n:02}")), "x").unwrap();
+        }
+        let cfg = dir.join("Link.toml");
+        let before = format!("[settings]\ndotlink_root = {:?}\n", dir.join("root"));
+        std::fs::write(&cfg, &before).unwrap();
+
+        assert!(dotlink(&cfg, &["add", &pattern(&dir, "home/f*")]).unwrap());
+        assert!(read(cfg.clone()).contains("f11"));
+        let backups = backup::list(&cfg).unwrap();
+        assert!(
+            backups
+                .iter()
+                .any(|backup| read(backup.path.clone()) == before)
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_link_that_cannot_be_made_does_not_stop_the_others() {
+        let dir = scratch("fix-unwritable");
+        std::fs::create_dir_all(dir.join("root")).unwrap();
+        std::fs::create_dir_all(dir.join("home")).unwrap();
+        std::fs::write(dir.join("root/a"), "a").unwrap();
+        std::fs::write(dir.join("root/b"), "b").unwrap();
+
+        // nothing can be created in `/proc`, not even by root
+        let unwritable = Path::new("/proc/dotlink-test-unwritable");
+        let cfg = dir.join("Link.toml");
+        let toml = format!(
+            "[settings]\ndotlink_root = {:?}\n\n[entries]\n{:?} = {:?}\n{:?} = {:?}\n",
+            dir.join("root"),
+            dir.join("root/a"),
+            unwritable,
+            dir.join("root/b"),
+            dir.join("home/b"),
+        );
+        std::fs::write(&cfg, toml).unwrap();
+
+        assert!(!dotlink(&cfg, &["fix"]).unwrap());
+        assert!(std::fs::symlink_metadata(unwritable).is_err());
+        assert_eq!(
+            std::fs::read_link(dir.join("home/b")).unwrap(),
+            dir.join("root/b")
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn malformed_config_exits_with_the_config_status() {
+        let dir = scratch("malformed-config");
+        let cfg = dir.join("Link.toml");
+        std::fs::write(&cfg, "[settings\ndotlink_root = ").unwrap();
+
+        let error = load_cfg(&cfg).unwrap_err();
+        assert!(matches!(error, Error::ConfigParse { .. }));
+        assert_eq!(error.exit_code(), EXIT_CONFIG);
+
+        let error = dotlink(&cfg, &["status"]).unwrap_err();
+        assert_eq!(error.exit_code(), EXIT_CONFIG);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Adds `home/app`, which holds a symlink to `elsewhere/real`, with `flags`
+    /// and returns what became of that symlink in the dotfiles root.
+    fn add_dir_with_symlink(test: &str, flags: &[&str]) -> (PathBuf, std::fs::Metadata) {
+        let dir = scratch(test);
+        std::fs::create_dir_all(dir.join("root")).unwrap();
+        std::fs::create_dir_all(dir.join("home/app")).unwrap();
+        std::fs::create_dir_all(dir.join("elsewhere")).unwrap();
+        std::fs::write(dir.join("elsewhere/real"), "real").unwrap();
+        std::os::unix::fs::symlink(dir.join("elsewhere/real"), dir.join("home/app/link")).unwrap();
+        let cfg = dir.join("Link.toml");
+        std::fs::write(
+            &cfg,
+            format!("[settings]\ndotlink_root = {:?}\n", dir.join("root")),
+        )
+        .unwrap();
+
+        let app = dir.join("home/app");
+        let args: Vec<&str> = ["add"]
+            .iter()
+            .chain(flags)
+            .copied()
+            .chain([app.to_str().unwrap()])
+            .collect();
+        assert!(dotlink(&cfg, &args).unwrap());
+        let metadata = std::fs::symlink_metadata(dir.join("root/app/link")).unwrap();
+        (dir, metadata)
+    }
+
+    #[test]
+    fn adding_a_directory_keeps_its_symlinks() {
+        let (dir, link) = add_dir_with_symlink("add-keeps-symlinks", &[]);
+        assert!(link.file_type().is_symlink());
+        assert_eq!(
+            std::fs::read_link(dir.join("root/app/link")).unwrap(),
+            dir.join("elsewhere/real")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn adding_a_directory_with_follow_symlinks_copies_their_files() {
+        let (dir, link) = add_dir_with_symlink("add-follows-symlinks", &["--follow-symlinks"]);
+        assert!(link.file_type().is_file());
+        assert_eq!(read(dir.join("root/app/link")), "real");
+        // what the symlink pointed to is left where it was
+        assert_eq!(read(dir.join("elsewhere/real")), "real");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// An entry for `root/app.conf` whose target is under `home/cfg`, a symlink
+    /// to the `real` directory. Returns the config.
+    fn symlinked_target_dir(dir: &Path) -> PathBuf {
+        std::fs::create_dir_all(dir.join("root")).unwrap();
+        std::fs::create_dir_all(dir.join("home")).unwrap();
+        std::fs::create_dir_all(dir.join("real")).unwrap();
+        std::fs::write(dir.join("root/app.conf"), "source").unwrap();
+        std::os::unix::fs::symlink(dir.join("real"), dir.join("home/cfg")).unwrap();
+
+        let cfg = dir.join("Link.toml");
+        let toml = format!(
+            "[settings]\ndotlink_root = {root:?}\n\n[entries]\n{source:?} = {target:?}\n",
+            root = dir.join("root"),
+            source = dir.join("root/app.conf"),
+            target = dir.join("home/cfg/app.conf"),
+        );
+        std::fs::write(&cfg, toml).unwrap();
+        cfg
+    }
+
+    #[test]
+    fn symlinked_target_dir_warns_and_links_through_it() {
+        let dir = scratch("dereference-warn");
+        let cfg = symlinked_target_dir(&dir);
+
+        assert!(dotlink(&cfg, &["fix", "--dereference-target", "warn"]).unwrap());
+        assert!(
+            std::fs::symlink_metadata(dir.join("home/cfg"))
+                .unwrap()
+                .is_symlink()
+        );
+        assert_eq!(
+            std::fs::read_link(dir.join("real/app.conf")).unwrap(),
+            dir.join("root/app.conf")
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn symlinked_target_dir_is_resolved() {
+        let dir = scratch("dereference-resolve");
+        let cfg = symlinked_target_dir(&dir);
+
+        assert!(dotlink(&cfg, &["fix", "--dereference-target", "resolve"]).unwrap());
+        assert!(
+            std::fs::symlink_metadata(dir.join("home/cfg"))
+                .unwrap()
+                .is_symlink()
+        );
+        assert_eq!(
+            std::fs::read_link(dir.join("real/app.conf")).unwrap(),
+            dir.join("root/app.conf")
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn symlinked_target_dir_is_refused() {
+        let dir = scratch("dereference-refuse");
+        let cfg = symlinked_target_dir(&dir);
+
+        assert!(!dotlink(&cfg, &["fix", "--dereference-target", "refuse"]).unwrap());
+        assert!(std::fs::symlink_metadata(dir.join("real/app.conf")).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn overlaps_root_cases() {
+        let dir = scratch("overlaps-root").canonicalize().unwrap();
+        let root = dir.join("dotfiles");
+        std::fs::create_dir_all(&root).unwrap();
+
+        assert!(overlaps_root(&root, &root));
+        assert!(overlaps_root(&root.join("vimrc"), &root));
+        assert!(overlaps_root(&dir, &root));
+        assert!(!overlaps_root(&dir.join("home/.vimrc"), &root));
+        // a shared prefix of the name is not an overlap
+        assert!(!overlaps_root(&dir.join("dotfiles2"), &root));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn check_reports_a_target_inside_the_root() {
+        let dir = scratch("check-overlap");
+        std::fs::create_dir_all(dir.join("root")).unwrap();
+        std::fs::write(dir.join("root/app.conf"), "source").unwrap();
+        let cfg = dir.join("Link.toml");
+        let toml = format!(
+            "[settings]\ndotlink_root = {root:?}\n\n[entries]\n{source:?} = {target:?}\n",
+            root = dir.join("root"),
+            source = dir.join("root/app.conf"),
+            target = dir.join("root/linked.conf"),
+        );
+        std::fs::write(&cfg, toml).unwrap();
+
+        assert!(!dotlink(&cfg, &["check"]).unwrap());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// `home/app` with `visible.conf` and the dotfile `.secret`, and an empty
+    /// dotfiles root. Returns the config.
+    fn dir_with_dotfile(dir: &Path) -> PathBuf {
+        std::fs::create_dir_all(dir.join("root")).unwrap();
+        std::fs::create_dir_all(dir.join("home/app")).unwrap();
+        std::fs::write(dir.join("home/app/visible.conf"), "visible").unwrap();
+        std::fs::write(dir.join("home/app/.secret"), "secret").unwrap();
+        let cfg = dir.join("Link.toml");
+        std::fs::write(
+            &cfg,
+            format!("[settings]\ndotlink_root = {:?}\n", dir.join("root")),
+        )
+        .unwrap();
+        cfg
+    }
+
+    #[test]
+    fn no_hidden_leaves_dotfiles_of_added_directories() {
+        let dir = scratch("add-no-hidden");
+        let cfg = dir_with_dotfile(&dir);
+
+        let app = dir.join("home/app");
+        assert!(dotlink(&cfg, &["add", "--no-hidden", app.to_str().unwrap()]).unwrap());
+        assert!(
+            std::fs::symlink_metadata(dir.join("home/app"))
+                .unwrap()
+                .is_dir()
+        );
+        assert!(
+            std::fs::symlink_metadata(dir.join("home/app/visible.conf"))
+                .unwrap()
+                .is_symlink()
+        );
+        assert_eq!(read(dir.join("root/visible.conf")), "visible");
+        assert!(
+            std::fs::symlink_metadata(dir.join("home/app/.secret"))
+                .unwrap()
+                .is_file()
+        );
+        assert!(!dir.join("root/.secret").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dotfiles_are_added_with_their_directory_by_default() {
+        let dir = scratch("add-hidden");
+        let cfg = dir_with_dotfile(&dir);
+
+        let app = dir.join("home/app");
+        assert!(dotlink(&cfg, &["add", app.to_str().unwrap()]).unwrap());
+        assert!(
+            std::fs::symlink_metadata(dir.join("home/app"))
+                .unwrap()
+                .is_symlink()
+        );
+        assert_eq!(read(dir.join("root/app/.secret")), "secret");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// An entry for `root/app.conf`, a symlink to `root/real.conf`, linked from
+    /// `home/app.conf`. Returns the config.
+    fn symlinked_source(dir: &Path) -> PathBuf {
+        std::fs::create_dir_all(dir.join("root")).unwrap();
+        std::fs::create_dir_all(dir.join("home")).unwrap();
+        std::fs::write(dir.join("root/real.conf"), "real").unwrap();
+        std::os::unix::fs::symlink(dir.join("root/real.conf"), dir.join("root/app.conf")).unwrap();
+
+        let cfg = dir.join("Link.toml");
+        let toml = format!(
+            "[settings]\ndotlink_root = {root:?}\n\n[entries]\n{source:?} = {target:?}\n",
+            root = dir.join("root"),
+            source = dir.join("root/app.conf"),
+            target = dir.join("home/app.conf"),
+        );
+        std::fs::write(&cfg, toml).unwrap();
+        cfg
+    }
+
+    #[test]
+    fn symlinked_source_is_linked_as_it_is() {
+        let dir = scratch("source-symlinks-keep");
+        let cfg = symlinked_source(&dir);
+
+        assert!(dotlink(&cfg, &["fix"]).unwrap());
+        assert_eq!(
+            std::fs::read_link(dir.join("home/app.conf")).unwrap(),
+            dir.join("root/app.conf")
+        );
+        assert!(dotlink(&cfg, &["status"]).unwrap());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn symlinked_source_is_resolved() {
+        let dir = scratch("source-symlinks-resolve");
+        let cfg = symlinked_source(&dir);
+        let real = dir.join("root/real.conf").canonicalize().unwrap();
+
+        assert!(dotlink(&cfg, &["fix", "--source-symlinks", "resolve"]).unwrap());
+        assert_eq!(std::fs::read_link(dir.join("home/app.conf")).unwrap(), real);
+        // status takes a link to where the source leads as correct too
+        assert!(dotlink(&cfg, &["status"]).unwrap());
+        // and a second run has nothing to change
+        assert!(dotlink(&cfg, &["fix", "--source-symlinks", "resolve", "--strict"]).unwrap());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Adds `home/app.conf` with `--prefix prefix`, returning the result and the scratch dir.
+    fn add_with_prefix(test: &str, prefix: &str) -> (Result<bool>, PathBuf) {
+        let dir = scratch(test);
+        std::fs::create_dir_all(dir.join("root")).unwrap();
+        std::fs::create_dir_all(dir.join("home")).unwrap();
+        std::fs::write(dir.join("home/app.conf"), "app").unwrap();
+        let cfg = dir.join("Link.toml");
+        std::fs::write(
+            &cfg,
+            format!("[settings]\ndotlink_root = {:?}\n", dir.join("root")),
+        )
+        .unwrap();
+
+        let file = dir.join("home/app.conf");
+        (
+            dotlink(&cfg, &["add", "--prefix", prefix, file.to_str().unwrap()]),
+            dir,
+        )
+    }
+
+    #[test]
+    fn prefix_leaving_the_root_is_rejected() {
+        let (result, dir) = add_with_prefix("prefix-parent", "../x");
+        assert!(matches!(result, Err(Error::InvalidPrefix(prefix)) if prefix == Path::new("../x")));
+        assert!(
+            std::fs::symlink_metadata(dir.join("home/app.conf"))
+                .unwrap()
+                .is_file()
+        );
+        assert!(!dir.join("x").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn absolute_prefix_is_rejected() {
+        let (result, dir) = add_with_prefix("prefix-absolute", "/tmp/x");
+        assert!(
+            matches!(result, Err(Error::InvalidPrefix(prefix)) if prefix == Path::new("/tmp/x"))
+        );
+        assert!(
+            std::fs::symlink_metadata(dir.join("home/app.conf"))
+                .unwrap()
+                .is_file()
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn relative_prefix_goes_into_the_root() {
+        let (result, dir) = add_with_prefix("prefix-relative", "pkg/app");
+        assert!(result.unwrap());
+        assert_eq!(read(dir.join("root/pkg/app/app.conf")), "app");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sources_differing_only_in_case_collide() {
+        let problems = case_collisions([
+            PathBuf::from("/root/Foo"),
+            PathBuf::from("/root/bar"),
+            PathBuf::from("/root/foo"),
+        ]);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("\"/root/Foo\" and \"/root/foo\""));
+    }
+
+    #[test]
+    fn same_source_twice_does_not_collide() {
+        // an entry with several targets comes up once per target
+        assert!(
+            case_collisions([PathBuf::from("/root/Foo"), PathBuf::from("/root/Foo")]).is_empty()
+        );
+    }
+
+    #[test]
+    fn collision_is_reported_once() {
+        let sources = ["/root/Foo", "/root/foo", "/root/foo"].map(PathBuf::from);
+        assert_eq!(case_collisions(sources).len(), 1);
+    }
+
+    #[test]
+    fn invalid_pattern_is_a_config_error() {
+        let dir = scratch("invalid-pattern");
+        let cfg = dir.join("Link.toml");
+        std::fs::write(&cfg, format!("[settings]\ndotlink_root = {:?}\n", dir)).unwrap();
+
+        let pattern = pattern(&dir, "[abc");
+        let err = dotlink(&cfg, &["add", &pattern]).unwrap_err();
+        assert!(matches!(&err, Error::InvalidPattern { pattern: p, .. } if *p == pattern));
+        assert_eq!(err.exit_code(), EXIT_CONFIG);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn files_with_dollar_and_percent_signs_can_be_added() {
+        let dir = scratch("add-escaped");
+        std::fs::create_dir_all(dir.join("root")).unwrap();
+        std::fs::create_dir_all(dir.join("home")).unwrap();
+        std::fs::write(dir.join("home/a$b"), "dollar").unwrap();
+        std::fs::write(dir.join("home/50%off%"), "percent").unwrap();
+        let cfg = dir.join("Link.toml");
+        std::fs::write(
+            &cfg,
+            format!("[settings]\ndotlink_root = {:?}\n", dir.join("root")),
+        )
+        .unwrap();
+
+        let (dollar, percent) = (pattern(&dir, "home/a$$b"), pattern(&dir, "home/50%%off%%"));
+        assert!(dotlink(&cfg, &["add", &dollar, &percent]).unwrap());
+        assert_eq!(read(dir.join("root/a$b")), "dollar");
+        assert_eq!(read(dir.join("root/50%off%")), "percent");
+        // recorded escaped, so the config reads back as the same files
+        assert!(dotlink(&cfg, &["status"]).unwrap());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unexpandable_pattern_is_a_config_error() {
+        let dir = scratch("unexpandable-pattern");
+        let cfg = dir.join("Link.toml");
+        std::fs::write(&cfg, format!("[settings]\ndotlink_root = {:?}\n", dir)).unwrap();
+
+        let err = dotlink(&cfg, &["add", "$DOTLINK_SURELY_UNSET_346/x"]).unwrap_err();
+        assert!(matches!(err, Error::Expand(_)));
+        assert!(err.to_string().contains("`DOTLINK_SURELY_UNSET_346`"));
+        assert_eq!(err.exit_code(), EXIT_CONFIG);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn targets_inside_other_targets_are_nested() {
+        let targets: HashSet<PathBuf> = [
+            "/h/.config",
+            "/h/.config/nvim",
+            "/h/.config/nvim/init.lua",
+            "/h/.configs",
+            "/h/.vimrc",
+        ]
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
+
+        assert_eq!(
+            nested_targets(&targets),
+            [
+                (
+                    PathBuf::from("/h/.config/nvim"),
+                    PathBuf::from("/h/.config")
+                ),
+                // the closest target above wins
+                (
+                    PathBuf::from("/h/.config/nvim/init.lua"),
+                    PathBuf::from("/h/.config/nvim")
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use serde::Deserialize;
+
+use crate::config::CONFIG_VERSION;
+use crate::error::Error;
+use crate::error::Result;
+
+#[derive(Deserialize)]
+struct Versioned {
+    version: Option<u32>,
+}
+
+/// The format version `contents` declares, 1 for configs from before there
+/// was one. `None` if it is not even valid TOML, which the full parse reports.
+fn version(contents: &str) -> Option<u32> {
+    let versioned = toml::from_str::<Versioned>(contents).ok()?;
+    Some(versioned.version.unwrap_or(1))
+}
+
+/// Refuses configs written for a newer dotlink, which could otherwise be
+/// misread, or fail to parse with a confusing error.
+pub fn check_version(contents: &str) -> Result<()> {
+    match version(contents) {
+        Some(version) if version > CONFIG_VERSION => Err(Error::InvalidConfig(format!(
+            "config version {version} is newer than this dotlink supports ({CONFIG_VERSION}), update dotlink"
+        ))),
+        Some(0) => Err(Error::InvalidConfig("config version 0 does not exist".to_string())),
+        _ => Ok(()),
+    }
+}
+
+/// Rewrites `contents` in the current format, editing the text so comments and
+/// layout survive. Returns the new text and a description of every change,
+/// none if it is up to date already.
+///
+/// Every format change gets a step here, taking configs of the version before
+/// it one version further.
+pub fn migrate(contents: &str) -> (String, Vec<String>) {
+    let mut contents = contents.to_string();
+    let mut changes = Vec::new();
+
+    let declared = toml::from_str::<Versioned>(&contents).ok().and_then(|v| v.version);
+    if declared.is_none() {
+        insert_version(&mut contents);
+        changes.push(format!("added `version = {CONFIG_VERSION}`"));
+    }
+
+    (contents, changes)
+}
+
+/// Adds the `version` key below the comments at the top of the file, where a
+/// top-level key is always allowed.
+fn insert_version(contents: &mut String) {
+    let at = contents
+        .split_inclusive('\n')
+        .take_while(|line| line.trim_start().starts_with('#'))
+        .map(str::len)
+        .sum();
+
+    let mut line = format!("version = {CONFIG_VERSION}\n");
+    if at > 0 {
+        line.insert(0, '\n');
+    }
+    if !contents[at..].trim_start_matches('\n').is_empty() && !contents[at..].starts_with('\n') {
+        line.push('\n');
+    }
+    contents.insert_str(at, &line);
+}
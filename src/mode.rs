@@ -0,0 +1,136 @@
+use std::fmt;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+
+use crate::theme;
+use crate::tracefs as fs;
+
+/// Permission bits, written as an octal string (`"0600"`) in the config and
+/// on the command line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mode(pub u32);
+
+impl Mode {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        u32::from_str_radix(s.trim_start_matches("0o"), 8)
+            .ok()
+            .filter(|mode| *mode <= 0o7777)
+            .map(Mode)
+            .ok_or_else(|| format!("invalid mode `{s}`, expected octal permissions like `0600`"))
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04o}", self.0)
+    }
+}
+
+impl Serialize for Mode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Mode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Mode::parse(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Sets `path` to `mode` unless it already has it.
+fn set(path: &Path, mode: Mode, dry_run: bool) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.file_type().is_symlink() || metadata.permissions().mode() & 0o7777 == mode.0 {
+        return Ok(());
+    }
+
+    println!(
+        "  - chmod {} {}",
+        mode,
+        theme::path(format!("{:?}", path.display()))
+    );
+
+    if dry_run {
+        return Ok(());
+    }
+    fs::set_permissions(path, PermissionsExt::from_mode(mode.0))
+}
+
+/// Creates `dir` and its missing parents like `create_dir_all` and, if `mode`
+/// is set, gives the directories it created that mode. Existing ones keep theirs.
+pub fn create_dir_all(dir: &Path, mode: Option<Mode>) -> io::Result<()> {
+    let missing: Vec<_> = dir
+        .ancestors()
+        .take_while(|dir| fs::symlink_metadata(dir).is_err())
+        .collect();
+
+    fs::create_dir_all(dir)?;
+
+    if let Some(mode) = mode {
+        // deepest first, so a mode without search permission doesn't lock us out
+        for dir in missing {
+            fs::set_permissions(dir, PermissionsExt::from_mode(mode.0))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies `files` and `dirs` to `path` and, for a directory, everything below
+/// it. `own` is used for `path` itself instead, if set. Symlinks are skipped.
+pub fn normalize(
+    path: &Path,
+    own: Option<Mode>,
+    files: Option<Mode>,
+    dirs: Option<Mode>,
+    dry_run: bool,
+) -> io::Result<()> {
+    let is_dir = fs::symlink_metadata(path)?.is_dir();
+
+    if let Some(mode) = own.or(if is_dir { dirs } else { files }) {
+        set(path, mode, dry_run)?;
+    }
+
+    if is_dir && (files.is_some() || dirs.is_some()) {
+        for child in fs::read_dir(path)? {
+            normalize(&child?.path(), None, files, dirs, dry_run)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::MetadataExt;
+
+    use super::*;
+
+    fn mode_of(path: &Path) -> u32 {
+        std::fs::metadata(path).unwrap().mode() & 0o7777
+    }
+
+    #[test]
+    fn created_directories_get_the_mode() {
+        let base = std::env::temp_dir().join(format!("dotlink-{}-dir-perms", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir(&base).unwrap();
+        std::fs::set_permissions(&base, PermissionsExt::from_mode(0o755)).unwrap();
+
+        create_dir_all(&base.join(".ssh/keys"), Some(Mode(0o700))).unwrap();
+        assert_eq!(mode_of(&base.join(".ssh")), 0o700);
+        assert_eq!(mode_of(&base.join(".ssh/keys")), 0o700);
+        // it existed before, so it is left as it was
+        assert_eq!(mode_of(&base), 0o755);
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
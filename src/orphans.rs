@@ -0,0 +1,141 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::config::Entry;
+use crate::error::Result;
+use crate::expand;
+use crate::repair;
+use crate::theme;
+use crate::tracefs as fs;
+
+/// A symlink into the dotfiles root that is not the target of any entry.
+pub struct Orphan {
+    /// Where the symlink is
+    pub target: PathBuf,
+    /// The file in the root it leads to
+    pub source: PathBuf,
+}
+
+/// Walks `dirs` down to `max_depth` levels below each of them and collects the
+/// symlinks into the root of `cfg` that no entry links. Symlinked directories
+/// and the root itself are not entered, unreadable directories are skipped
+/// with a warning.
+pub fn find(cfg: &Config, dirs: &[PathBuf], max_depth: usize) -> Result<Vec<Orphan>> {
+    let root = cfg.get_root()?;
+    let targets: HashSet<_> = cfg
+        .entries_under(&root)?
+        .filter_map(|entry| cfg.resolve_target(&entry.target).ok())
+        .collect();
+
+    let mut seen = HashSet::new();
+    let mut orphans = Vec::new();
+    for dir in dirs {
+        walk(dir, max_depth, &root, &targets, &mut seen, &mut orphans);
+    }
+
+    Ok(orphans)
+}
+
+fn walk(
+    dir: &Path,
+    depth: usize,
+    root: &Path,
+    targets: &HashSet<PathBuf>,
+    seen: &mut HashSet<PathBuf>,
+    orphans: &mut Vec<Orphan>,
+) {
+    // overlapping search directories would list the same links twice
+    if dir.starts_with(root) || !seen.insert(dir.to_path_buf()) {
+        return;
+    }
+
+    let children = match fs::read_dir(dir) {
+        Ok(children) => children,
+        Err(e) => {
+            eprintln!(
+                "  {} Cannot search {:?}: {e}",
+                theme::warning("Warning:"),
+                dir
+            );
+            return;
+        }
+    };
+
+    let mut paths: Vec<_> = children
+        .filter_map(|child| child.ok())
+        .map(|child| child.path())
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let Ok(metadata) = fs::symlink_metadata(&path) else {
+            continue;
+        };
+
+        if metadata.is_dir() {
+            if depth > 0 {
+                walk(&path, depth - 1, root, targets, seen, orphans);
+            }
+            continue;
+        }
+
+        if !metadata.is_symlink() || targets.contains(&path) {
+            continue;
+        }
+
+        let Some(source) = source_in_root(&path, root) else {
+            continue;
+        };
+
+        orphans.push(Orphan {
+            target: path,
+            source,
+        });
+    }
+}
+
+/// The existing file in `root` the symlink at `link` leads to, if it leads
+/// into the root at all.
+pub fn source_in_root(link: &Path, root: &Path) -> Option<PathBuf> {
+    // links written through a symlinked path still count when they end up in the root
+    repair::link_destination(link)
+        .and_then(|dest| {
+            if dest.starts_with(root) {
+                Some(dest)
+            } else {
+                fs::canonicalize(dest).ok()
+            }
+        })
+        .filter(|source| source.starts_with(root) && source.exists())
+}
+
+/// Records a link at `target`, as written in the config, to `source` in
+/// `root`: as one more target of the entry that has `source` already, or as a
+/// new entry. Returns false if that entry is in an included file, which
+/// dotlink does not write to.
+pub fn adopt(cfg: &mut Config, root: &Path, source: PathBuf, target: PathBuf) -> bool {
+    let is_source = |key: &PathBuf| Config::resolve_source(root, key).is_ok_and(|key| key == source);
+    let own = cfg.own_entries().find(|(key, _)| is_source(key)).map(|(key, _)| key.clone());
+    let included = cfg.included.iter().find(|(key, _)| is_source(key)).map(|(_, (file, _))| file.clone());
+
+    if let Some(key) = own {
+        // another link to a source that is already managed becomes one more target
+        let entry = cfg.entries_containing(&key).get_mut(&key).expect("entry exists");
+        entry.targets.push(target);
+    } else if let Some(file) = included {
+        eprintln!(
+            "  {} {:?} is managed in {:?}, add {:?} there.",
+            theme::warning("Warning:"),
+            source,
+            file,
+            target
+        );
+        return false;
+    } else {
+        cfg.entries.insert(expand::escape(&source), Entry::new(target));
+    }
+
+    true
+}
@@ -0,0 +1,87 @@
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use crate::privileged::Batch;
+use crate::privileged::Op;
+use crate::theme;
+use crate::tracefs as fs;
+
+/// The uid of `owner` and the gid of `group`, each given as a name or a number.
+pub fn resolve(owner: Option<&str>, group: Option<&str>) -> io::Result<(Option<u32>, Option<u32>)> {
+    let uid = owner
+        .map(|owner| id("/etc/passwd", "user", owner))
+        .transpose()?;
+    let gid = group
+        .map(|group| id("/etc/group", "group", group))
+        .transpose()?;
+    Ok((uid, gid))
+}
+
+fn id(db: &str, kind: &str, name: &str) -> io::Result<u32> {
+    if let Ok(id) = name.parse() {
+        return Ok(id);
+    }
+
+    // name:password:id:...
+    fs::read_to_string(db)?
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() >= 3 && fields[0] == name)
+        .and_then(|fields| fields[2].parse().ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("no such {kind} `{name}`"),
+            )
+        })
+}
+
+/// `uid` and `gid` the way `chown` takes them: `uid:gid`, `uid` or `:gid`.
+pub fn spec(uid: Option<u32>, gid: Option<u32>) -> String {
+    match (uid, gid) {
+        (Some(uid), Some(gid)) => format!("{uid}:{gid}"),
+        (Some(uid), None) => uid.to_string(),
+        (None, Some(gid)) => format!(":{gid}"),
+        (None, None) => String::new(),
+    }
+}
+
+/// Gives `path` the owner `uid` and group `gid` where it has others, itself or,
+/// if `batch` is given, through it as root. Symlinks are changed, not followed.
+pub fn enforce(
+    path: &Path,
+    uid: Option<u32>,
+    gid: Option<u32>,
+    dry_run: bool,
+    batch: Option<&mut Batch>,
+) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    let uid = uid.filter(|uid| *uid != metadata.uid());
+    let gid = gid.filter(|gid| *gid != metadata.gid());
+    if uid.is_none() && gid.is_none() {
+        return Ok(());
+    }
+
+    let op = Op::Chown {
+        path: path.to_path_buf(),
+        uid,
+        gid,
+    };
+    if let Some(batch) = batch.filter(|_| !dry_run) {
+        batch.push(op);
+        return Ok(());
+    }
+
+    println!(
+        "  - {} (was {}:{})",
+        theme::path(&op),
+        metadata.uid(),
+        metadata.gid()
+    );
+
+    if dry_run {
+        return Ok(());
+    }
+    fs::lchown(path, uid, gid)
+}
@@ -0,0 +1,138 @@
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::ensure_parent;
+use crate::link::LinkKind;
+use crate::mode::Mode;
+use crate::privileged::Batch;
+use crate::privileged::Op;
+use crate::tracefs as fs;
+
+/// Version of the plan format, raised when it changes incompatibly.
+pub const PLAN_VERSION: u32 = 1;
+
+/// The links `fix` would make, written by `plan` and carried out by
+/// `apply-from`, possibly on another machine.
+#[derive(Serialize, Deserialize)]
+pub struct Plan {
+    pub version: u32,
+    /// The dotfiles root the plan was made for
+    pub root: PathBuf,
+    pub actions: Vec<Action>,
+}
+
+/// One change of a `Plan`. Paths are absolute.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    /// Create a link at `target`, where nothing is yet
+    Link {
+        source: PathBuf,
+        target: PathBuf,
+        kind: LinkKind,
+        privileged: bool,
+    },
+    /// Replace the symlink at `target`, which points to `points_to`
+    Repoint {
+        source: PathBuf,
+        target: PathBuf,
+        kind: LinkKind,
+        privileged: bool,
+        points_to: PathBuf,
+    },
+}
+
+impl Action {
+    pub fn source(&self) -> &Path {
+        match self {
+            Action::Link { source, .. } | Action::Repoint { source, .. } => source,
+        }
+    }
+
+    pub fn target(&self) -> &Path {
+        match self {
+            Action::Link { target, .. } | Action::Repoint { target, .. } => target,
+        }
+    }
+
+    /// How the filesystem differs from what the plan expected for this action,
+    /// if it does.
+    pub fn drift(&self) -> Option<String> {
+        if fs::symlink_metadata(self.source()).is_err() {
+            return Some(format!("source {:?} does not exist", self.source()));
+        }
+
+        match self {
+            Action::Link { target, .. } => match fs::symlink_metadata(target) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(e) => Some(format!("cannot inspect {target:?}: {e}")),
+                Ok(_) => Some(format!("{target:?} exists")),
+            },
+            Action::Repoint {
+                target, points_to, ..
+            } => match fs::read_link(target) {
+                Ok(link) if link == *points_to => None,
+                Ok(link) => Some(format!("{target:?} points to {link:?}, not {points_to:?}")),
+                Err(e) => Some(format!("{target:?} is not the expected symlink: {e}")),
+            },
+        }
+    }
+
+    /// Makes the change, through `batch` for privileged entries. Missing parent
+    /// directories are created with `dir_perms` if `parents` is set.
+    pub fn apply(
+        &self,
+        parents: bool,
+        dir_perms: Option<Mode>,
+        batch: &mut Batch,
+    ) -> io::Result<()> {
+        match self {
+            Action::Link {
+                source,
+                target,
+                kind,
+                privileged: true,
+            } => {
+                match target.parent() {
+                    Some(parent) if parents => batch.push(Op::CreateDir(parent.to_path_buf())),
+                    _ => ensure_parent(target, false, None)?,
+                }
+                batch.push(Op::link(*kind, source.clone(), target.clone()));
+            }
+            Action::Link {
+                source,
+                target,
+                kind,
+                ..
+            } => {
+                ensure_parent(target, parents, dir_perms)?;
+                kind.create(source, target)?;
+            }
+            Action::Repoint {
+                source,
+                target,
+                kind,
+                privileged: true,
+                ..
+            } => {
+                batch.push(Op::Remove(target.clone()));
+                batch.push(Op::link(*kind, source.clone(), target.clone()));
+            }
+            Action::Repoint {
+                source,
+                target,
+                kind,
+                ..
+            } => {
+                fs::remove_file(target)?;
+                kind.create(source, target)?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+//! Checks for `fix --dry-run` whether the changes it lists would go through,
+//! without making any of them.
+
+use std::io;
+use std::path::Path;
+
+use rustix::fs::Access;
+
+use crate::tracefs as fs;
+
+/// Why the dotfiles root could not be read, if it couldn't.
+pub fn root(root: &Path) -> Option<String> {
+    fs::access(root, Access::READ_OK | Access::EXEC_OK)
+        .err()
+        .map(|e| describe(e, root))
+}
+
+/// Why creating (or replacing) the link at `target` would fail, if it would.
+///
+/// Privileged entries are changed through sudo, so for them only a missing
+/// parent is reported, not whether we could write to it ourselves.
+pub fn link(target: &Path, parents: bool, privileged: bool) -> Option<String> {
+    let dir = target.parent()?;
+
+    // missing parents are created, which needs the closest existing ancestor
+    let writable = match fs::exists(dir) {
+        Ok(true) => dir,
+        Ok(false) if !parents => return Some(format!("parent directory {dir:?} does not exist")),
+        Ok(false) => dir.ancestors().find(|a| fs::exists(a).unwrap_or(false))?,
+        Err(e) => return Some(describe(e, dir)),
+    };
+
+    if !fs::metadata(writable).is_ok_and(|m| m.is_dir()) {
+        return Some(format!("{writable:?} is not a directory"));
+    }
+
+    if privileged {
+        return None;
+    }
+
+    fs::access(writable, Access::WRITE_OK | Access::EXEC_OK)
+        .err()
+        .map(|e| match e.kind() {
+            io::ErrorKind::PermissionDenied => format!(
+                "permission denied, {writable:?} is not writable (does the entry need `privileged = true`?)"
+            ),
+            _ => describe(e, writable),
+        })
+}
+
+fn describe(e: io::Error, path: &Path) -> String {
+    match e.kind() {
+        io::ErrorKind::PermissionDenied => format!("permission denied for {path:?}"),
+        _ => format!("{path:?}: {e}"),
+    }
+}
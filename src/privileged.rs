@@ -0,0 +1,131 @@
+use std::ffi::OsString;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::link::LinkKind;
+use crate::owner;
+use crate::theme;
+use crate::tracefs;
+
+/// A filesystem change that has to run as root.
+pub enum Op {
+    CreateDir(PathBuf),
+    Symlink { source: PathBuf, target: PathBuf },
+    HardLink { source: PathBuf, target: PathBuf },
+    Remove(PathBuf),
+    /// Removes an empty directory
+    RemoveDir(PathBuf),
+    Move { from: PathBuf, to: PathBuf },
+    /// Changes the owner and/or group, without following a symlink
+    Chown {
+        path: PathBuf,
+        uid: Option<u32>,
+        gid: Option<u32>,
+    },
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::CreateDir(path) => write!(f, "mkdir -p {path:?}"),
+            Op::Symlink { source, target } => write!(f, "ln -s {source:?} {target:?}"),
+            Op::HardLink { source, target } => write!(f, "ln {source:?} {target:?}"),
+            Op::Remove(path) => write!(f, "rm {path:?}"),
+            Op::RemoveDir(path) => write!(f, "rmdir {path:?}"),
+            Op::Move { from, to } => write!(f, "mv {from:?} {to:?}"),
+            Op::Chown { path, uid, gid } => write!(f, "chown {} {path:?}", owner::spec(*uid, *gid)),
+        }
+    }
+}
+
+impl Op {
+    /// Creates a link of `kind` at `target` for `source`.
+    pub fn link(kind: LinkKind, source: PathBuf, target: PathBuf) -> Op {
+        match kind {
+            LinkKind::Symlink => Op::Symlink { source, target },
+            LinkKind::Hardlink => Op::HardLink { source, target },
+            LinkKind::Alias => unreachable!("privileged entries can't be Finder aliases, see `Config::check_links`"),
+        }
+    }
+}
+
+/// Privileged operations collected during a run, executed together through a
+/// single `sudo` invocation so the password is asked for at most once.
+#[derive(Default)]
+pub struct Batch {
+    ops: Vec<Op>,
+}
+
+impl Batch {
+    pub fn push(&mut self, op: Op) {
+        self.ops.push(op);
+    }
+
+    pub fn run(self) -> io::Result<()> {
+        if self.ops.is_empty() {
+            return Ok(());
+        }
+
+        println!(
+            "[{}] Running {} privileged operation(s) through sudo:",
+            theme::info("INFO"),
+            self.ops.len()
+        );
+
+        // paths are passed as positional parameters so they never need quoting
+        let mut script = String::from("set -e");
+        let mut args: Vec<OsString> = Vec::new();
+        for op in &self.ops {
+            println!("  - {}", theme::path(op));
+
+            let n = args.len();
+            match op {
+                Op::CreateDir(path) => {
+                    script.push_str(&format!("; mkdir -p -- \"${{{}}}\"", n + 1));
+                    args.push(path.into());
+                }
+                Op::Symlink { source, target } => {
+                    script.push_str(&format!("; ln -s -- \"${{{}}}\" \"${{{}}}\"", n + 1, n + 2));
+                    args.extend([source.into(), target.into()]);
+                }
+                Op::HardLink { source, target } => {
+                    script.push_str(&format!("; ln -- \"${{{}}}\" \"${{{}}}\"", n + 1, n + 2));
+                    args.extend([source.into(), target.into()]);
+                }
+                Op::Remove(path) => {
+                    script.push_str(&format!("; rm -- \"${{{}}}\"", n + 1));
+                    args.push(path.into());
+                }
+                Op::RemoveDir(path) => {
+                    script.push_str(&format!("; rmdir -- \"${{{}}}\"", n + 1));
+                    args.push(path.into());
+                }
+                Op::Move { from, to } => {
+                    script.push_str(&format!("; mv -- \"${{{}}}\" \"${{{}}}\"", n + 1, n + 2));
+                    args.extend([from.into(), to.into()]);
+                }
+                Op::Chown { path, uid, gid } => {
+                    script.push_str(&format!("; chown -h -- \"${{{}}}\" \"${{{}}}\"", n + 1, n + 2));
+                    args.extend([owner::spec(*uid, *gid).into(), path.into()]);
+                }
+            }
+        }
+
+        let status = Command::new("sudo")
+            .args(["--", "sh", "-c", &script, "sh"])
+            .args(&args)
+            .status()?;
+
+        let result = if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!(
+                "privileged operations failed ({status})"
+            )))
+        };
+
+        tracefs::changed("sudo", &[("script", &script), ("args", &args)], result)
+    }
+}
@@ -0,0 +1,99 @@
+use std::io;
+use std::io::IsTerminal;
+use std::io::Write;
+
+/// Whether we can ask the user questions.
+pub fn is_interactive() -> bool {
+    io::stdin().is_terminal()
+}
+
+fn read_answer(question: &str) -> io::Result<String> {
+    print!("{question} ");
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().to_string())
+}
+
+/// Asks a yes/no question, anything but `y`/`yes` counts as no.
+pub fn confirm(question: &str) -> io::Result<bool> {
+    let answer = read_answer(&format!("{question} [y/N]"))?;
+    Ok(matches!(answer.to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Asks before each of a series of changes, until the user answers `a`.
+#[derive(Default)]
+pub struct ConfirmEach {
+    all: bool,
+}
+
+impl ConfirmEach {
+    /// Asks a yes/no/all question, nothing counts as no and `a` says yes to
+    /// this and every later question.
+    pub fn ask(&mut self, question: &str) -> io::Result<bool> {
+        if self.all {
+            return Ok(true);
+        }
+
+        loop {
+            let answer = read_answer(&format!("{question} [y/N/a]"))?.to_lowercase();
+            match answer.as_str() {
+                "y" | "yes" => return Ok(true),
+                "" | "n" | "no" => return Ok(false),
+                "a" | "all" => {
+                    self.all = true;
+                    return Ok(true);
+                }
+                _ => println!("Invalid answer `{answer}`, expected `y`, `n` or `a`."),
+            }
+        }
+    }
+}
+
+/// Lists `items` with numbers and lets the user pick some of them.
+///
+/// Answers are numbers and ranges separated by spaces or commas (`1 3-5`),
+/// `a` for all, or nothing for none. Returns the selected indices in order.
+pub fn select(items: &[String]) -> io::Result<Vec<usize>> {
+    for (i, item) in items.iter().enumerate() {
+        println!("  {:>3}) {item}", i + 1);
+    }
+
+    loop {
+        let answer = read_answer("Select entries (e.g. `1 3-5`, `a` for all, empty for none):")?;
+        match parse_selection(&answer, items.len()) {
+            Some(selected) => return Ok(selected),
+            None => println!("Invalid selection `{answer}`."),
+        }
+    }
+}
+
+fn parse_selection(answer: &str, len: usize) -> Option<Vec<usize>> {
+    if answer.eq_ignore_ascii_case("a") {
+        return Some((0..len).collect());
+    }
+
+    let mut selected = Vec::new();
+    for part in answer.split([' ', ',']).filter(|p| !p.is_empty()) {
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => (start.parse::<usize>().ok()?, end.parse::<usize>().ok()?),
+            None => {
+                let n = part.parse::<usize>().ok()?;
+                (n, n)
+            }
+        };
+
+        if start == 0 || end > len || start > end {
+            return None;
+        }
+
+        for i in start - 1..end {
+            if !selected.contains(&i) {
+                selected.push(i);
+            }
+        }
+    }
+
+    Some(selected)
+}
@@ -0,0 +1,125 @@
+use std::collections::BTreeSet;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+
+use path_clean::PathClean;
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::template;
+use crate::tracefs as fs;
+
+/// A proposed update to the `[entries]` of the main config.
+pub enum Change {
+    /// A symlink into the root that no entry knows about
+    Add { source: PathBuf, target: PathBuf },
+    /// The source is gone, but the entry's link points to another file in the root
+    Rekey { old: PathBuf, new: PathBuf },
+    /// The source is gone and nothing points to where it went
+    Remove { key: PathBuf },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::Add { source, target } => write!(f, "add {source:?} = {target:?}"),
+            Change::Rekey { old, new } => write!(f, "rename {old:?} to {new:?}"),
+            Change::Remove { key } => write!(f, "remove {key:?}, its source no longer exists"),
+        }
+    }
+}
+
+/// Compares the config with the root and the links on disk and proposes changes.
+///
+/// Unmanaged links are only looked for next to the targets of existing entries
+/// and directly in the target base, not in the whole home directory.
+pub fn plan(cfg: &Config) -> Result<Vec<Change>> {
+    let root = cfg.get_root()?;
+    let mut changes = Vec::new();
+
+    let mut sources = HashSet::new();
+    let mut targets = HashSet::new();
+    let mut dirs = BTreeSet::new();
+    for entry in cfg.entries_under(&root)? {
+        if let Ok(target) = cfg.resolve_target(&entry.target) {
+            if let Some(parent) = target.parent() {
+                dirs.insert(parent.to_path_buf());
+            }
+            targets.insert(target);
+        }
+        sources.insert(entry.source);
+    }
+    if let Ok(base) = cfg.resolve_target(Path::new("")) {
+        dirs.insert(base);
+    }
+
+    // entries of the main config whose source disappeared
+    for (key, entry) in cfg.own_entries() {
+        if entry.targets.iter().any(|target| template::is_template(target)) {
+            continue;
+        }
+
+        let source = Config::resolve_source(&root, key)?;
+        if fs::symlink_metadata(&source).is_ok() {
+            continue;
+        }
+
+        let moved_to = entry
+            .targets
+            .iter()
+            .filter_map(|target| cfg.resolve_target(target).ok())
+            .filter_map(|target| link_destination(&target))
+            .find(|dest| dest.starts_with(&root) && dest.exists() && !sources.contains(dest));
+
+        match moved_to {
+            Some(new) => {
+                sources.insert(new.clone());
+
+                // keep relative keys relative
+                let new = match new.strip_prefix(&root) {
+                    Ok(rel) if key.is_relative() => rel.to_path_buf(),
+                    _ => new,
+                };
+
+                changes.push(Change::Rekey {
+                    old: key.clone(),
+                    new,
+                });
+            }
+            None => changes.push(Change::Remove { key: key.clone() }),
+        }
+    }
+
+    // links into the root that are not managed yet
+    for dir in dirs {
+        let Ok(children) = fs::read_dir(&dir) else {
+            continue;
+        };
+
+        for child in children {
+            let target = child?.path();
+            if targets.contains(&target) || target.starts_with(&root) {
+                continue;
+            }
+
+            if let Some(source) = link_destination(&target)
+                && source.starts_with(&root)
+                && source.exists()
+                && sources.insert(source.clone())
+            {
+                changes.push(Change::Add { source, target });
+            }
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Where the symlink at `path` points, made absolute. `None` if it is not a symlink.
+pub fn link_destination(path: &Path) -> Option<PathBuf> {
+    let dest = fs::read_link(path).ok()?;
+    let dir = path.parent().unwrap_or(Path::new("/"));
+    Some(dir.join(dest).clean())
+}
@@ -0,0 +1,61 @@
+use std::io;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use crate::theme;
+
+const DEFAULT_RETRIES: u32 = 2;
+const DEFAULT_DELAY_MS: u64 = 100;
+
+/// Retries read-only filesystem calls that fail with errors known to be
+/// transient on network mounts, doubling the delay after every attempt.
+pub struct Retry {
+    retries: u32,
+    delay: Duration,
+}
+
+impl Retry {
+    pub fn new(retries: Option<u32>, delay_ms: Option<u64>) -> Self {
+        Self {
+            retries: retries.unwrap_or(DEFAULT_RETRIES),
+            delay: Duration::from_millis(delay_ms.unwrap_or(DEFAULT_DELAY_MS)),
+        }
+    }
+
+    pub fn run<T>(&self, path: &Path, mut op: impl FnMut(&Path) -> io::Result<T>) -> io::Result<T> {
+        let mut delay = self.delay;
+        let mut attempt = 0;
+
+        loop {
+            match op(path) {
+                Err(e) if is_transient(&e) && attempt < self.retries => {
+                    attempt += 1;
+                    thread::sleep(delay);
+                    delay *= 2;
+                }
+                Err(e) if is_transient(&e) && attempt > 0 => {
+                    eprintln!(
+                        "  {} Giving up on {:?} after {} attempts: {e}",
+                        theme::warning("Warning:"),
+                        path,
+                        attempt + 1
+                    );
+                    return Err(e);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// `NotFound`, `PermissionDenied` and the like are definitive answers, only
+/// errors a flaky NFS/SMB mount produces are worth another try.
+fn is_transient(e: &io::Error) -> bool {
+    const EIO: i32 = 5;
+
+    matches!(
+        e.kind(),
+        io::ErrorKind::TimedOut | io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock
+    ) || e.raw_os_error() == Some(EIO)
+}
@@ -0,0 +1,122 @@
+//! A persistent history of runs, enabled with `settings.log_file` or
+//! `--log-file`. Every run appends its command, the changes it made to the
+//! filesystem (the calls `--trace` shows that change something) and its
+//! outcome, each line prefixed with the time and the process id:
+//!
+//! ```text
+//! 2026-01-02T10:00:00Z [4242] start: dotlink fix
+//! 2026-01-02T10:00:00Z [4242] op=symlink source="/root/dots/vimrc" target="/home/user/.vimrc" result=ok
+//! 2026-01-02T10:00:00Z [4242] end: ok, 1 change(s)
+//! ```
+
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::expand::expand_path;
+use crate::since;
+
+/// Size above which the log is moved to `<log>.1` (replacing an older one)
+/// before a run starts writing to it.
+const MAX_SIZE: u64 = 1024 * 1024;
+
+/// `settings.log_file`: `true` for the default location, or a path.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum LogFile {
+    Enabled(bool),
+    Path(PathBuf),
+}
+
+impl LogFile {
+    /// Where to log, `None` if disabled.
+    fn path(&self) -> io::Result<Option<PathBuf>> {
+        match self {
+            LogFile::Enabled(false) => Ok(None),
+            LogFile::Enabled(true) => default_path().map(Some),
+            LogFile::Path(path) => expand_path(path).map(Some),
+        }
+    }
+}
+
+/// `$XDG_STATE_HOME/dotlink/log`, or `~/.local/state/dotlink/log`.
+fn default_path() -> io::Result<PathBuf> {
+    let state = match env::var_os("XDG_STATE_HOME").map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => dir,
+        _ => expand_path(Path::new("~/.local/state"))?,
+    };
+    Ok(state.join("dotlink").join("log"))
+}
+
+struct Log {
+    file: File,
+    changes: usize,
+}
+
+static LOG: Mutex<Option<Log>> = Mutex::new(None);
+
+/// Opens (and if needed rotates) the log of `log_file`, if enabled, and records
+/// the start of the run.
+///
+/// The log is written with `std::fs` directly, so it doesn't show up in itself.
+pub fn start(log_file: &LogFile) -> io::Result<()> {
+    let Some(path) = log_file.path()? else {
+        return Ok(());
+    };
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    if fs::metadata(&path).is_ok_and(|m| m.len() > MAX_SIZE) {
+        let mut rotated = path.as_os_str().to_owned();
+        rotated.push(".1");
+        fs::rename(&path, rotated)?;
+    }
+
+    let mut file = File::options().create(true).append(true).open(&path)?;
+    let command = ["dotlink".to_string()]
+        .into_iter()
+        .chain(env::args().skip(1))
+        .collect::<Vec<_>>()
+        .join(" ");
+    write_to(&mut file, &format!("start: {command}"));
+
+    *LOG.lock().expect("log lock poisoned") = Some(Log { file, changes: 0 });
+    Ok(())
+}
+
+/// Records a change, `line` is only built if logging is on.
+pub fn change(line: impl FnOnce() -> String) {
+    let mut log = LOG.lock().expect("log lock poisoned");
+    if let Some(log) = log.as_mut() {
+        log.changes += 1;
+        write_to(&mut log.file, &line());
+    }
+}
+
+/// Records how the run ended, if logging is on.
+pub fn finish(outcome: &str) {
+    let mut log = LOG.lock().expect("log lock poisoned");
+    if let Some(mut log) = log.take() {
+        let line = format!("end: {outcome}, {} change(s)", log.changes);
+        write_to(&mut log.file, &line);
+    }
+}
+
+/// Appends `line` with the time and process id. A log that can't be written
+/// must not fail the run, so errors are ignored.
+fn write_to(file: &mut File, line: &str) {
+    let time = since::format_utc(SystemTime::now());
+    let _ = writeln!(file, "{time} [{}] {line}", process::id());
+}
@@ -0,0 +1,112 @@
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+/// Parses the argument of `fix --since` into a point in time.
+///
+/// Accepted forms:
+/// - a duration before now, made of `<n><unit>` parts with units `s`, `m`,
+///   `h`, `d` and `w` (`10m`, `2h`, `1h30m`)
+/// - `@<seconds>`, a Unix timestamp
+/// - `YYYY-MM-DD`, optionally followed by `THH:MM` or `THH:MM:SS`, in UTC
+pub fn parse_since(arg: &str) -> Result<SystemTime, String> {
+    if let Some(secs) = arg.strip_prefix('@') {
+        let secs = secs
+            .parse::<u64>()
+            .map_err(|_| format!("invalid Unix timestamp `{arg}`"))?;
+        return Ok(UNIX_EPOCH + Duration::from_secs(secs));
+    }
+
+    if arg.len() >= 10 && arg.as_bytes()[4] == b'-' {
+        return parse_date(arg).ok_or_else(|| format!("invalid date `{arg}`"));
+    }
+
+    let ago = parse_duration(arg)?;
+    SystemTime::now()
+        .checked_sub(ago)
+        .ok_or_else(|| format!("duration `{arg}` is too long"))
+}
+
+fn parse_duration(arg: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration `{arg}`, expected something like `10m` or `2h`");
+
+    if arg.is_empty() {
+        return Err(invalid());
+    }
+
+    let mut total = 0u64;
+    let mut rest = arg;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+        let n = rest[..digits].parse::<u64>().map_err(|_| invalid())?;
+
+        let mut chars = rest[digits..].chars();
+        let unit = match chars.next() {
+            Some('s') => 1,
+            Some('m') => 60,
+            Some('h') => 60 * 60,
+            Some('d') => 24 * 60 * 60,
+            Some('w') => 7 * 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+
+        total += n * unit;
+        rest = chars.as_str();
+    }
+
+    Ok(Duration::from_secs(total))
+}
+
+fn parse_date(arg: &str) -> Option<SystemTime> {
+    let (date, time) = arg.split_once('T').unwrap_or((arg, "00:00:00"));
+
+    let mut date = date.splitn(3, '-').map(|p| p.parse::<i64>().ok());
+    let (y, m, d) = (date.next()??, date.next()??, date.next()??);
+
+    let mut time = time.splitn(3, ':').map(|p| p.parse::<i64>().ok());
+    let (hh, mm) = (time.next()??, time.next()??);
+    let ss = time.next().unwrap_or(Some(0))?;
+
+    if !(1..=12).contains(&m) || !(1..=31).contains(&d) || hh > 23 || mm > 59 || ss > 60 {
+        return None;
+    }
+
+    let secs = days_from_civil(y, m, d) * 86400 + hh * 3600 + mm * 60 + ss;
+    Some(UNIX_EPOCH + Duration::from_secs(u64::try_from(secs).ok()?))
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date (Howard Hinnant's algorithm).
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (m + if m > 2 { -3 } else { 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// `time` as `YYYY-MM-DDTHH:MM:SSZ`, the inverse of the date form `parse_since` accepts.
+pub fn format_utc(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as i64;
+    let (y, m, d) = civil_from_days(secs.div_euclid(86400));
+    let secs = secs.rem_euclid(86400);
+    format!(
+        "{y:04}-{m:02}-{d:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// The date `days` after 1970-01-01, the inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    (if m <= 2 { yoe + era * 400 + 1 } else { yoe + era * 400 }, m, d)
+}
@@ -0,0 +1,132 @@
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::theme;
+use crate::tracefs as fs;
+
+/// How many of the biggest entries `status --stats` lists.
+const LARGEST: usize = 5;
+
+/// Disk usage of one entry's source.
+#[derive(Serialize)]
+pub struct EntrySize {
+    pub name: PathBuf,
+    pub source: PathBuf,
+    /// Bytes allocated on disk, for directories everything below them
+    pub size: u64,
+}
+
+/// What `status --stats` reports: the size of every source that exists and
+/// their total.
+#[derive(Serialize)]
+pub struct Stats {
+    pub total: u64,
+    pub entries: Vec<EntrySize>,
+    /// Names of the biggest entries, largest first
+    pub largest: Vec<PathBuf>,
+}
+
+impl Stats {
+    /// Measures the sources of all entries of `cfg`, disabled ones included.
+    /// Missing sources are reported on stderr and left out.
+    pub fn collect(cfg: &Config) -> Result<Self> {
+        let mut entries = Vec::new();
+        for entry in cfg.entries()? {
+            match size(&entry.source) {
+                Ok(size) => entries.push(EntrySize {
+                    name: entry.name,
+                    source: entry.source,
+                    size,
+                }),
+                Err(e) => eprintln!(
+                    "  {} Skipping {:?}: {e}",
+                    theme::warning("Warning:"),
+                    entry.name
+                ),
+            }
+        }
+
+        let mut by_size: Vec<_> = entries.iter().collect();
+        by_size.sort_by_key(|entry| std::cmp::Reverse(entry.size));
+        let largest = by_size
+            .into_iter()
+            .take(LARGEST)
+            .map(|entry| entry.name.clone())
+            .collect();
+
+        Ok(Stats {
+            total: entries.iter().map(|entry| entry.size).sum(),
+            entries,
+            largest,
+        })
+    }
+
+    pub fn print(&self) {
+        for entry in &self.entries {
+            println!("{:>10}  {:?}", human(entry.size), entry.name);
+        }
+
+        if !self.largest.is_empty() {
+            println!("\nLargest:");
+            for name in &self.largest {
+                let entry = self.entries.iter().find(|e| e.name == *name);
+                let size = entry.map_or(0, |e| e.size);
+                println!("{:>10}  {}", human(size), theme::path(format!("{name:?}")));
+            }
+        }
+
+        println!(
+            "\nTotal: {} in {} entries",
+            human(self.total),
+            self.entries.len()
+        );
+    }
+}
+
+/// Space allocated for `path` and, for a directory, everything below it, like
+/// `du`. Symlinks are not followed and hard linked files count once per link.
+fn size(path: &Path) -> io::Result<u64> {
+    let metadata = fs::symlink_metadata(path)?;
+    let mut size = metadata.blocks() * 512;
+
+    if metadata.is_dir() {
+        for child in fs::read_dir(path)? {
+            // something unreadable deep down shouldn't hide the size of the rest
+            let child = child?.path();
+            size += self::size(&child).unwrap_or_else(|e| {
+                eprintln!(
+                    "  {} Cannot measure {:?}: {e}",
+                    theme::warning("Warning:"),
+                    child
+                );
+                0
+            });
+        }
+    }
+
+    Ok(size)
+}
+
+/// `bytes` in binary units with one decimal, `4.0 KiB`.
+fn human(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
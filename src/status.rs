@@ -0,0 +1,302 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::alias;
+use crate::config::Config;
+use crate::config::Origin;
+use crate::config::ResolvedEntry;
+use crate::error::Result;
+use crate::link;
+use crate::link::LinkKind;
+use crate::retry::Retry;
+use crate::tracefs as fs;
+
+/// What `status` found for one entry, without changing anything.
+#[derive(Clone, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum State {
+    Ok,
+    /// Nothing exists at the target
+    Missing,
+    /// The target exists but is not the expected symlink or hard link
+    Conflict,
+    /// The target is a symlink pointing somewhere else
+    Mismatch { points_to: PathBuf },
+    /// The source does not exist in the dotfiles root
+    SourceMissing,
+    Disabled,
+    /// The target could not be resolved or inspected
+    Error { error: String },
+}
+
+/// The name of a `State`, as written in JSON output, for `status --filter-state`.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+#[value(rename_all = "snake_case")]
+pub enum StateName {
+    Ok,
+    Missing,
+    Conflict,
+    Mismatch,
+    SourceMissing,
+    Disabled,
+    Error,
+}
+
+impl State {
+    pub fn name(&self) -> StateName {
+        match self {
+            State::Ok => StateName::Ok,
+            State::Missing => StateName::Missing,
+            State::Conflict => StateName::Conflict,
+            State::Mismatch { .. } => StateName::Mismatch,
+            State::SourceMissing => StateName::SourceMissing,
+            State::Disabled => StateName::Disabled,
+            State::Error { .. } => StateName::Error,
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct EntryStatus {
+    pub name: PathBuf,
+    /// Resolved target, or the target as written if it cannot be resolved
+    pub target: PathBuf,
+    pub source: PathBuf,
+    #[serde(flatten)]
+    pub state: State,
+    /// Which part of the config the entry comes from
+    #[serde(skip)]
+    pub origin: Origin,
+}
+
+/// Number of entries in each state.
+#[derive(Default, Serialize)]
+pub struct Summary {
+    pub ok: usize,
+    pub missing: usize,
+    pub conflict: usize,
+    pub mismatch: usize,
+    pub source_missing: usize,
+    pub error: usize,
+    pub disabled: usize,
+}
+
+impl Summary {
+    fn count(&mut self, state: &State) {
+        let count = match state {
+            State::Ok => &mut self.ok,
+            State::Missing => &mut self.missing,
+            State::Conflict => &mut self.conflict,
+            State::Mismatch { .. } => &mut self.mismatch,
+            State::SourceMissing => &mut self.source_missing,
+            State::Disabled => &mut self.disabled,
+            State::Error { .. } => &mut self.error,
+        };
+        *count += 1;
+    }
+
+    /// Whether every enabled entry is linked correctly.
+    pub fn all_ok(&self) -> bool {
+        self.missing + self.conflict + self.mismatch + self.source_missing + self.error == 0
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "ok={} missing={} conflict={} mismatch={} source_missing={} error={} disabled={}",
+            self.ok,
+            self.missing,
+            self.conflict,
+            self.mismatch,
+            self.source_missing,
+            self.error,
+            self.disabled
+        )
+    }
+}
+
+/// Inspects every entry of `cfg` (read only), handing each result to
+/// `on_entry` as soon as it is known.
+pub fn inspect(cfg: &Config, mut on_entry: impl FnMut(&EntryStatus)) -> Result<Summary> {
+    let retry = Retry::new(cfg.settings.retries, cfg.settings.retry_delay_ms);
+    let mut summary = Summary::default();
+
+    for entry in cfg.entries()? {
+        let status = inspect_entry(cfg, &retry, entry);
+        summary.count(&status.state);
+        on_entry(&status);
+    }
+
+    Ok(summary)
+}
+
+/// Inspects one entry of `cfg` (read only).
+pub fn inspect_entry(cfg: &Config, retry: &Retry, entry: ResolvedEntry) -> EntryStatus {
+    let origin = entry.origin;
+    let (target, state) = match cfg.resolve_target(&entry.target) {
+        _ if !entry.enabled => (entry.target, State::Disabled),
+        Err(e) => (entry.target, State::Error { error: e.to_string() }),
+        Ok(target) => {
+            let state = inspect_target(retry, &entry.source, &target, entry.link);
+            (target, state)
+        }
+    };
+
+    EntryStatus {
+        name: entry.name,
+        target,
+        source: entry.source,
+        state,
+        origin,
+    }
+}
+
+pub fn inspect_target(retry: &Retry, source: &Path, target: &Path, kind: LinkKind) -> State {
+    if fs::symlink_metadata(source).is_err() {
+        return State::SourceMissing;
+    }
+
+    match retry.run(target, |p| fs::symlink_metadata(p)) {
+        Ok(metadata) if metadata.file_type().is_symlink() => {
+            match retry.run(target, |p| fs::read_link(p)) {
+                Ok(_) if kind != LinkKind::Symlink => State::Conflict,
+                Ok(link) if link == *source || leads_to(source, &link) => State::Ok,
+                Ok(link) => State::Mismatch { points_to: link },
+                Err(e) => State::Error { error: e.to_string() },
+            }
+        }
+        Ok(_) if kind == LinkKind::Hardlink && link::same_file(source, target) => State::Ok,
+        Ok(_) if kind == LinkKind::Alias => match alias::original(target) {
+            Ok(Some(original)) if alias::leads_to(&original, source) => State::Ok,
+            Ok(Some(original)) => State::Mismatch { points_to: original },
+            Ok(None) => State::Conflict,
+            Err(e) => State::Error { error: e.to_string() },
+        },
+        Ok(_) => State::Conflict,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => State::Missing,
+        Err(e) => State::Error { error: e.to_string() },
+    }
+}
+
+/// Whether `source` is a symlink itself that ends up at `link`, as linked by
+/// `fix --source-symlinks resolve`.
+fn leads_to(source: &Path, link: &Path) -> bool {
+    fs::symlink_metadata(source).is_ok_and(|m| m.is_symlink())
+        && fs::canonicalize(source).is_ok_and(|resolved| resolved == link)
+}
+
+/// The document written by `--report-file`.
+#[derive(Serialize)]
+pub struct Report<'a> {
+    pub entries: &'a [EntryStatus],
+    pub summary: &'a Summary,
+}
+
+impl Report<'_> {
+    /// Writes the report as JSON to `path`, creating missing parent directories.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+
+        let json = serde_json::to_string_pretty(self).expect("failed to serialize report");
+        fs::write_synced(path, json + "\n")
+    }
+}
+
+/// One line of `resolve --json` output: an entry's status and, if its target is
+/// a symlink, the link as stored.
+#[derive(Serialize)]
+pub struct Resolved<'a> {
+    #[serde(flatten)]
+    pub status: &'a EntryStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link: Option<PathBuf>,
+}
+
+/// One line of `status --json-lines` output.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record<'a> {
+    Entry(&'a EntryStatus),
+    Summary(&'a Summary),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `dir/real` and the symlink `dir/source` leading to it.
+    fn symlinked_source(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dotlink-{}-{test}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("real"), "real").unwrap();
+        std::os::unix::fs::symlink(dir.join("real"), dir.join("source")).unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    fn state(dir: &Path, link_to: &Path) -> State {
+        std::os::unix::fs::symlink(link_to, dir.join("target")).unwrap();
+        inspect_target(
+            &Retry::new(None, None),
+            &dir.join("source"),
+            &dir.join("target"),
+            LinkKind::Symlink,
+        )
+    }
+
+    #[test]
+    fn link_to_a_symlinked_source_is_ok() {
+        let dir = symlinked_source("status-source-kept");
+        assert!(matches!(state(&dir, &dir.join("source")), State::Ok));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn link_to_where_a_symlinked_source_leads_is_ok() {
+        let dir = symlinked_source("status-source-resolved");
+        assert!(matches!(state(&dir, &dir.join("real")), State::Ok));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn link_elsewhere_is_a_mismatch() {
+        let dir = symlinked_source("status-source-elsewhere");
+        std::fs::write(dir.join("other"), "real").unwrap();
+        assert!(matches!(
+            state(&dir, &dir.join("other")),
+            State::Mismatch { .. }
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn hard_link_is_judged_by_inode() {
+        let dir = symlinked_source("status-hardlink");
+        let retry = Retry::new(None, None);
+        std::fs::hard_link(dir.join("real"), dir.join("linked")).unwrap();
+        std::fs::copy(dir.join("real"), dir.join("copied")).unwrap();
+
+        let state = |target: &str| {
+            inspect_target(
+                &retry,
+                &dir.join("real"),
+                &dir.join(target),
+                LinkKind::Hardlink,
+            )
+        };
+        assert!(matches!(state("linked"), State::Ok));
+        // same contents, but another file that won't see changes to the source
+        assert!(!matches!(state("copied"), State::Ok));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
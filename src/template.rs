@@ -0,0 +1,188 @@
+use std::env;
+use std::io;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+use glob::glob;
+
+use crate::tracefs as fs;
+
+/// Placeholders that turn an entry into a template:
+///
+/// - `{rel}`: path of the matched file relative to the glob base, i.e. the
+///   part of the source pattern before its first wildcard component
+/// - `{name}`: file name of the matched file
+/// - `{stem}`: file name without its last extension
+const PLACEHOLDERS: [&str; 3] = ["{rel}", "{name}", "{stem}"];
+
+pub fn is_template(target: &Path) -> bool {
+    let target = target.to_string_lossy();
+    PLACEHOLDERS.iter().any(|p| target.contains(p))
+}
+
+/// Expands a templated entry into one `(relative source, target)` pair per file
+/// matched by the `source` glob. Directories matched by the pattern are skipped,
+/// so only files get linked.
+pub fn expand(root: &Path, source: &Path, target: &Path) -> io::Result<Vec<(PathBuf, PathBuf)>> {
+    let pattern = root.join(source);
+    let base = glob_base(&pattern);
+
+    let matches = glob(&pattern.to_string_lossy()).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid source pattern {source:?}: {e}"),
+        )
+    })?;
+
+    let target = target.to_string_lossy();
+    let mut expanded = Vec::new();
+
+    for path in matches {
+        let path = path.map_err(|e| e.into_error())?;
+        if path.is_dir() {
+            continue;
+        }
+
+        let rel = path.strip_prefix(&base).unwrap_or(&path);
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+        let resolved = target
+            .replace("{rel}", &rel.to_string_lossy())
+            .replace("{name}", &name)
+            .replace("{stem}", &stem);
+
+        let source = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+        expanded.push((source, PathBuf::from(resolved)));
+    }
+
+    Ok(expanded)
+}
+
+/// Replaces the built-in variables in a target:
+///
+/// - `{{hostname}}`: name of this machine
+/// - `{{os}}`: operating system, e.g. `linux` or `macos`
+/// - `{{arch}}`: CPU architecture, e.g. `x86_64` or `aarch64`
+/// - `{{user}}`: name of the current user
+///
+/// Anything else in double braces is an error, to catch typos.
+pub fn expand_builtins(target: &Path) -> io::Result<PathBuf> {
+    let raw = target.to_string_lossy();
+    if !raw.contains("{{") {
+        return Ok(target.to_path_buf());
+    }
+
+    let mut out = String::with_capacity(raw.len());
+    let mut rest = &*raw;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| invalid(format!("unterminated `{{{{` in target {target:?}")))?;
+
+        out.push_str(&builtin(&after[..end])?);
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+
+    Ok(PathBuf::from(out))
+}
+
+fn builtin(name: &str) -> io::Result<String> {
+    match name {
+        "hostname" => hostname(),
+        "os" => Ok(env::consts::OS.to_string()),
+        "arch" => Ok(env::consts::ARCH.to_string()),
+        "user" => env::var("USER")
+            .or_else(|_| env::var("LOGNAME"))
+            .map_err(|_| invalid("cannot expand `{{user}}`: neither `USER` nor `LOGNAME` is set".into())),
+        _ => Err(invalid(format!(
+            "unknown variable `{{{{{name}}}}}`, expected one of hostname, os, arch, user"
+        ))),
+    }
+}
+
+fn hostname() -> io::Result<String> {
+    if let Ok(name) = env::var("HOSTNAME") {
+        return Ok(name);
+    }
+
+    ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .map(|name| name.trim().to_string())
+        .find(|name| !name.is_empty())
+        .ok_or_else(|| invalid("cannot expand `{{hostname}}`: hostname is unknown".into()))
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+fn glob_base(pattern: &Path) -> PathBuf {
+    pattern
+        .components()
+        .take_while(|c| match c {
+            Component::Normal(part) => !part.to_string_lossy().contains(['*', '?', '[']),
+            _ => true,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builtins(target: &str) -> io::Result<PathBuf> {
+        expand_builtins(Path::new(target))
+    }
+
+    #[test]
+    fn hostname_from_environment() {
+        // SAFETY: no other test reads or writes `HOSTNAME`
+        unsafe { env::set_var("HOSTNAME", "box") };
+        assert_eq!(
+            builtins("~/logs/{{hostname}}/app.conf").unwrap(),
+            Path::new("~/logs/box/app.conf")
+        );
+    }
+
+    #[test]
+    fn os_and_arch() {
+        let expected = format!("/opt/{}-{}/bin", env::consts::OS, env::consts::ARCH);
+        assert_eq!(
+            builtins("/opt/{{os}}-{{arch}}/bin").unwrap(),
+            Path::new(&expected)
+        );
+    }
+
+    #[test]
+    fn user_from_environment() {
+        // SAFETY: no other test reads or writes `USER`
+        unsafe { env::set_var("USER", "alice") };
+        assert_eq!(
+            builtins("/home/{{user}}/.conf").unwrap(),
+            Path::new("/home/alice/.conf")
+        );
+    }
+
+    #[test]
+    fn targets_without_variables_are_kept() {
+        assert_eq!(
+            builtins("~/.config/{name}").unwrap(),
+            Path::new("~/.config/{name}")
+        );
+    }
+
+    #[test]
+    fn unknown_and_unterminated_variables_are_errors() {
+        let unknown = builtins("~/{{home}}/app.conf").unwrap_err();
+        assert_eq!(unknown.kind(), io::ErrorKind::InvalidInput);
+        assert!(unknown.to_string().contains("`{{home}}`"));
+
+        assert!(builtins("~/{{hostname/app.conf").is_err());
+    }
+}
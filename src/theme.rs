@@ -0,0 +1,146 @@
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use clap::ValueEnum;
+use colored::Color;
+use colored::ColoredString;
+use colored::Colorize;
+use serde::Deserialize;
+use serde::Serialize;
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum ThemeName {
+    #[default]
+    Dark,
+    Light,
+    Mono,
+}
+
+/// `[settings.colors]`, overrides the colors of the selected theme per role.
+///
+/// Values are color names understood by `colored` (`red`, `bright blue`, ...)
+/// or `none` for uncolored output.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Colors {
+    pub info: Option<String>,
+    pub warning: Option<String>,
+    pub error: Option<String>,
+    pub ok: Option<String>,
+    pub path: Option<String>,
+    pub action: Option<String>,
+}
+
+/// Semantic color of each kind of output, `None` meaning uncolored.
+struct Theme {
+    info: Option<Color>,
+    warning: Option<Color>,
+    error: Option<Color>,
+    ok: Option<Color>,
+    path: Option<Color>,
+    action: Option<Color>,
+}
+
+impl Theme {
+    fn builtin(name: ThemeName) -> Self {
+        match name {
+            ThemeName::Dark => Theme {
+                info: Some(Color::Yellow),
+                warning: Some(Color::Yellow),
+                error: Some(Color::Red),
+                ok: Some(Color::Green),
+                path: Some(Color::Cyan),
+                action: Some(Color::Blue),
+            },
+            ThemeName::Light => Theme {
+                info: Some(Color::Blue),
+                warning: Some(Color::Magenta),
+                error: Some(Color::Red),
+                ok: Some(Color::Green),
+                path: Some(Color::Black),
+                action: Some(Color::Blue),
+            },
+            ThemeName::Mono => Theme {
+                info: None,
+                warning: None,
+                error: None,
+                ok: None,
+                path: None,
+                action: None,
+            },
+        }
+    }
+}
+
+/// Selects the theme for the rest of the run. `NO_COLOR` always wins over
+/// both the theme and the configured colors.
+pub fn init(name: ThemeName, colors: &Colors) -> Result<(), String> {
+    if std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+        let _ = THEME.set(Theme::builtin(ThemeName::Mono));
+        return Ok(());
+    }
+
+    let mut theme = Theme::builtin(name);
+    for (slot, value) in [
+        (&mut theme.info, &colors.info),
+        (&mut theme.warning, &colors.warning),
+        (&mut theme.error, &colors.error),
+        (&mut theme.ok, &colors.ok),
+        (&mut theme.path, &colors.path),
+        (&mut theme.action, &colors.action),
+    ] {
+        if let Some(value) = value {
+            *slot = parse_color(value)?;
+        }
+    }
+
+    let _ = THEME.set(theme);
+    Ok(())
+}
+
+fn parse_color(value: &str) -> Result<Option<Color>, String> {
+    if value.eq_ignore_ascii_case("none") {
+        return Ok(None);
+    }
+
+    Color::from_str(value)
+        .map(Some)
+        .map_err(|_| format!("unknown color `{value}` in [settings.colors]"))
+}
+
+fn paint(color: fn(&Theme) -> Option<Color>, text: impl Display) -> ColoredString {
+    let theme = THEME.get_or_init(|| Theme::builtin(ThemeName::default()));
+    match color(theme) {
+        Some(color) => text.to_string().color(color),
+        None => text.to_string().normal(),
+    }
+}
+
+/// Progress tags such as `[INFO]`.
+pub fn info(text: impl Display) -> ColoredString {
+    paint(|t| t.info, text)
+}
+
+pub fn warning(text: impl Display) -> ColoredString {
+    paint(|t| t.warning, text)
+}
+
+pub fn error(text: impl Display) -> ColoredString {
+    paint(|t| t.error, text)
+}
+
+/// Successful changes.
+pub fn ok(text: impl Display) -> ColoredString {
+    paint(|t| t.ok, text)
+}
+
+pub fn path(text: impl Display) -> ColoredString {
+    paint(|t| t.path, text)
+}
+
+/// Changes that are about to be made.
+pub fn action(text: impl Display) -> ColoredString {
+    paint(|t| t.action, text)
+}
@@ -0,0 +1,229 @@
+//! Thin wrappers around the filesystem calls dotlink makes, with the same
+//! signatures as their `std` counterparts. With `--trace` every call is logged
+//! to stderr together with its result, one `key=value` line per call:
+//!
+//! ```text
+//! trace: op=symlink source="/root/dots/vimrc" target="/home/user/.vimrc" result=ok
+//! ```
+//!
+//! Calls that change something are also written to the run log, see `runlog`.
+
+use std::fmt::Debug;
+use std::fs;
+use std::fs::Metadata;
+use std::fs::Permissions;
+use std::fs::ReadDir;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+use filetime::FileTime;
+
+use crate::runlog;
+
+static TRACE: AtomicBool = AtomicBool::new(false);
+
+pub fn enable() {
+    TRACE.store(true, Ordering::Relaxed);
+}
+
+/// Logs `op` with its arguments and result when tracing is on, and passes the result through.
+pub fn traced<T>(op: &str, args: &[(&str, &dyn Debug)], result: io::Result<T>) -> io::Result<T> {
+    if TRACE.load(Ordering::Relaxed) {
+        eprintln!("trace: {}", describe(op, args, &result));
+    }
+
+    result
+}
+
+/// Like `traced`, for calls that change the filesystem, which are recorded in the run log too.
+pub fn changed<T>(op: &str, args: &[(&str, &dyn Debug)], result: io::Result<T>) -> io::Result<T> {
+    runlog::change(|| describe(op, args, &result));
+    traced(op, args, result)
+}
+
+fn describe<T>(op: &str, args: &[(&str, &dyn Debug)], result: &io::Result<T>) -> String {
+    let mut line = format!("op={op}");
+    for (key, value) in args {
+        line.push_str(&format!(" {key}={value:?}"));
+    }
+
+    match result {
+        Ok(_) => line.push_str(" result=ok"),
+        Err(e) => line.push_str(&format!(" result=err error={:?}", e.to_string())),
+    }
+    line
+}
+
+pub fn exists<P: AsRef<Path>>(path: P) -> io::Result<bool> {
+    let path = path.as_ref();
+    traced("exists", &[("path", &path)], fs::exists(path))
+}
+
+pub fn metadata<P: AsRef<Path>>(path: P) -> io::Result<Metadata> {
+    let path = path.as_ref();
+    traced("metadata", &[("path", &path)], fs::metadata(path))
+}
+
+pub fn symlink_metadata<P: AsRef<Path>>(path: P) -> io::Result<Metadata> {
+    let path = path.as_ref();
+    traced(
+        "symlink_metadata",
+        &[("path", &path)],
+        fs::symlink_metadata(path),
+    )
+}
+
+pub fn read_link<P: AsRef<Path>>(path: P) -> io::Result<PathBuf> {
+    let path = path.as_ref();
+    traced("read_link", &[("path", &path)], fs::read_link(path))
+}
+
+pub fn canonicalize<P: AsRef<Path>>(path: P) -> io::Result<PathBuf> {
+    let path = path.as_ref();
+    traced("canonicalize", &[("path", &path)], fs::canonicalize(path))
+}
+
+pub fn read_dir<P: AsRef<Path>>(path: P) -> io::Result<ReadDir> {
+    let path = path.as_ref();
+    traced("read_dir", &[("path", &path)], fs::read_dir(path))
+}
+
+pub fn read_to_string<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    let path = path.as_ref();
+    traced(
+        "read_to_string",
+        &[("path", &path)],
+        fs::read_to_string(path),
+    )
+}
+
+/// Like `std::fs::write`, but also flushes the contents to disk before returning.
+pub fn write_synced<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> io::Result<()> {
+    let path = path.as_ref();
+    let result = fs::File::create(path).and_then(|mut file| {
+        file.write_all(contents.as_ref())?;
+        file.sync_all()
+    });
+    changed("write_synced", &[("path", &path)], result)
+}
+
+pub fn create_dir<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    let path = path.as_ref();
+    changed("create_dir", &[("path", &path)], fs::create_dir(path))
+}
+
+pub fn create_dir_all<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    let path = path.as_ref();
+    changed(
+        "create_dir_all",
+        &[("path", &path)],
+        fs::create_dir_all(path),
+    )
+}
+
+pub fn remove_file<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    let path = path.as_ref();
+    changed("remove_file", &[("path", &path)], fs::remove_file(path))
+}
+
+pub fn remove_dir<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    let path = path.as_ref();
+    changed("remove_dir", &[("path", &path)], fs::remove_dir(path))
+}
+
+pub fn remove_dir_all<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    let path = path.as_ref();
+    changed(
+        "remove_dir_all",
+        &[("path", &path)],
+        fs::remove_dir_all(path),
+    )
+}
+
+pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> io::Result<()> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+    changed(
+        "rename",
+        &[("from", &from), ("to", &to)],
+        fs::rename(from, to),
+    )
+}
+
+pub fn copy<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> io::Result<u64> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+    changed("copy", &[("from", &from), ("to", &to)], fs::copy(from, to))
+}
+
+pub fn symlink<P: AsRef<Path>, Q: AsRef<Path>>(source: P, target: Q) -> io::Result<()> {
+    let (source, target) = (source.as_ref(), target.as_ref());
+    changed(
+        "symlink",
+        &[("source", &source), ("target", &target)],
+        std::os::unix::fs::symlink(source, target),
+    )
+}
+
+pub fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(source: P, target: Q) -> io::Result<()> {
+    let (source, target) = (source.as_ref(), target.as_ref());
+    changed(
+        "hard_link",
+        &[("source", &source), ("target", &target)],
+        fs::hard_link(source, target),
+    )
+}
+
+pub fn set_permissions<P: AsRef<Path>>(path: P, perm: Permissions) -> io::Result<()> {
+    let path = path.as_ref();
+    let mode = std::os::unix::fs::PermissionsExt::mode(&perm) & 0o7777;
+    changed(
+        "set_permissions",
+        &[("path", &path), ("mode", &format_args!("{mode:o}"))],
+        fs::set_permissions(path, perm),
+    )
+}
+
+pub fn lchown<P: AsRef<Path>>(path: P, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+    let path = path.as_ref();
+    changed(
+        "lchown",
+        &[("path", &path), ("uid", &uid), ("gid", &gid)],
+        std::os::unix::fs::lchown(path, uid, gid),
+    )
+}
+
+pub fn set_xattr<P: AsRef<Path>>(path: P, name: &std::ffi::OsStr, value: &[u8]) -> io::Result<()> {
+    let path = path.as_ref();
+    changed(
+        "set_xattr",
+        &[("path", &path), ("name", &name)],
+        xattr::set(path, name, value),
+    )
+}
+
+pub fn set_symlink_file_times<P: AsRef<Path>>(
+    path: P,
+    atime: FileTime,
+    mtime: FileTime,
+) -> io::Result<()> {
+    let path = path.as_ref();
+    changed(
+        "set_symlink_file_times",
+        &[("path", &path)],
+        filetime::set_symlink_file_times(path, atime, mtime),
+    )
+}
+
+/// Whether the effective user could access `path` in the given way, like `access(2)`.
+pub fn access<P: AsRef<Path>>(path: P, mode: rustix::fs::Access) -> io::Result<()> {
+    let path = path.as_ref();
+    traced(
+        "access",
+        &[("path", &path), ("mode", &mode)],
+        rustix::fs::accessat(rustix::fs::CWD, path, mode, rustix::fs::AtFlags::EACCESS)
+            .map_err(io::Error::from),
+    )
+}
@@ -0,0 +1,112 @@
+use std::collections::BTreeMap;
+use std::path::Component;
+
+use colored::Colorize;
+use serde::Serialize;
+
+use crate::status::EntryStatus;
+use crate::status::State;
+use crate::theme;
+
+/// A directory or link in the tree of targets. Directories with a single child
+/// and no entry of their own are merged into it, so `/home/user/.config` is one
+/// node rather than three.
+#[derive(Serialize)]
+pub struct Node<'a> {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entry: Option<&'a EntryStatus>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    children: Vec<Node<'a>>,
+}
+
+#[derive(Default)]
+struct Dir<'a> {
+    entry: Option<&'a EntryStatus>,
+    children: BTreeMap<String, Dir<'a>>,
+}
+
+/// Groups `entries` by the components of their targets.
+pub fn build(entries: &[EntryStatus]) -> Vec<Node<'_>> {
+    let mut root = Dir::default();
+    for entry in entries {
+        let mut dir = &mut root;
+        for component in entry.target.components() {
+            let name = match component {
+                Component::RootDir => "/".to_string(),
+                other => other.as_os_str().to_string_lossy().into_owned(),
+            };
+            dir = dir.children.entry(name).or_default();
+        }
+        dir.entry = Some(entry);
+    }
+
+    root.children
+        .into_iter()
+        .map(|(name, dir)| node(name, dir))
+        .collect()
+}
+
+fn node(name: String, dir: Dir<'_>) -> Node<'_> {
+    if dir.entry.is_none() && dir.children.len() == 1 {
+        let (child, dir) = dir.children.into_iter().next().expect("one child");
+        let joined = if name.ends_with('/') {
+            format!("{name}{child}")
+        } else {
+            format!("{name}/{child}")
+        };
+        return node(joined, dir);
+    }
+
+    Node {
+        name,
+        entry: dir.entry,
+        children: dir
+            .children
+            .into_iter()
+            .map(|(name, dir)| node(name, dir))
+            .collect(),
+    }
+}
+
+/// Prints `nodes` with box-drawing lines, each link with its source and state.
+pub fn print(nodes: &[Node]) {
+    for node in nodes {
+        println!("{}", line(node));
+        print_children(&node.children, "");
+    }
+}
+
+fn print_children(nodes: &[Node], prefix: &str) {
+    for (i, node) in nodes.iter().enumerate() {
+        let last = i + 1 == nodes.len();
+        println!(
+            "{prefix}{}{}",
+            if last { "└── " } else { "├── " },
+            line(node)
+        );
+        print_children(
+            &node.children,
+            &format!("{prefix}{}", if last { "    " } else { "│   " }),
+        );
+    }
+}
+
+fn line(node: &Node) -> String {
+    let Some(entry) = node.entry else {
+        return theme::path(&node.name).to_string();
+    };
+
+    let line = format!("{} <- {:?}", node.name, entry.source);
+    match &entry.state {
+        State::Ok => format!("{line} [ok]").bold().to_string(),
+        State::Missing => theme::action(format!("{line} [missing]")).to_string(),
+        State::Conflict => theme::error(format!("{line} [conflict]")).to_string(),
+        State::Mismatch { points_to } => {
+            theme::warning(format!("{line} [mismatch, points to {points_to:?}]")).to_string()
+        }
+        State::SourceMissing => theme::error(format!("{line} [source missing]")).to_string(),
+        State::Disabled => format!("{line} [disabled]"),
+        State::Error { error } => theme::error(format!("{line} [error: {error}]")).to_string(),
+    }
+}